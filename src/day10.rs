@@ -1,209 +1,777 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::cmp::{max, min, Ord, Ordering, PartialOrd};
+use failure::{format_err, Error};
+use std::cmp::max;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt;
-use std::ops::Range;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-#[derive(Eq)]
+/// A star, at `(x, y)` now and at `origin` when the sky is first seen.
+/// Every second, its velocity changes by its acceleration before it moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Point {
     x: i32,
     y: i32,
+    origin: (i32, i32),
     velocity: (i32, i32),
+    acceleration: (i32, i32),
 }
 
-type RawPoint = ((i32, i32), (i32, i32));
-
 impl Point {
-    fn advance(&mut self) {
-        self.x += self.velocity.0 as i32;
-        self.y += self.velocity.1 as i32;
+    /// Position `t` seconds after the start, `t` being possibly negative.
+    /// `None` if it doesn't fit in an `i32`.
+    fn at(&self, t: i32) -> Option<(i32, i32)> {
+        let t = i64::from(t);
+        // with |t| at most 2^31, neither `t (t + 1)` nor `v t` can overflow
+        let steps = t * (t + 1) / 2;
+        let axis = |x: i32, v: i32, a: i32| {
+            let x = i64::from(a)
+                .checked_mul(steps)?
+                .checked_add(i64::from(v) * t)?
+                .checked_add(i64::from(x))?;
+            i32::try_from(x).ok()
+        };
+        Some((
+            axis(self.origin.0, self.velocity.0, self.acceleration.0)?,
+            axis(self.origin.1, self.velocity.1, self.acceleration.1)?,
+        ))
     }
+}
 
-    fn rewind(&mut self) {
-        self.x -= self.velocity.0 as i32;
-        self.y -= self.velocity.1 as i32;
-    }
+/// Skips whitespace then `token`.
+fn expect<'a>(s: &'a str, token: &str) -> Result<&'a str, Error> {
+    let s = s.trim_start();
+    s.strip_prefix(token)
+        .ok_or_else(|| format_err!("expected \"{}\" at \"{}\"", token, s))
 }
 
-impl From<RawPoint> for Point {
-    fn from(i: RawPoint) -> Self {
-        Point {
-            x: (i.0).0,
-            y: (i.0).1,
-            velocity: i.1,
-        }
-    }
+fn number(s: &str) -> Result<(i32, &str), Error> {
+    let s = s.trim_start();
+    let end = s
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(s.len(), |(i, _)| i);
+    let n = s[..end]
+        .parse()
+        .map_err(|_| format_err!("expected a number at \"{}\"", s))?;
+    Ok((n, &s[end..]))
 }
 
-impl Ord for Point {
-    fn cmp(&self, other: &Point) -> Ordering {
-        self.y.cmp(&other.y).then_with(|| self.x.cmp(&other.x))
-    }
+/// Parses `name=<a, b>`, returning the rest of the input.
+fn pair<'a>(s: &'a str, name: &str) -> Result<((i32, i32), &'a str), Error> {
+    let s = expect(expect(expect(s, name)?, "=")?, "<")?;
+    let (a, s) = number(s)?;
+    let (b, s) = number(expect(s, ",")?)?;
+    Ok(((a, b), expect(s, ">")?))
 }
 
-impl PartialOrd for Point {
-    fn partial_cmp(&self, other: &Point) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl FromStr for Point {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ((x, y), s) = pair(s, "position")?;
+        let (velocity, mut s) = pair(s, "velocity")?;
+        let mut acceleration = (0, 0);
+        if s.trim_start().starts_with("acceleration") {
+            let (a, rest) = pair(s, "acceleration")?;
+            acceleration = a;
+            s = rest;
+        }
+        if !s.trim().is_empty() {
+            return Err(format_err!("unexpected \"{}\"", s.trim()));
+        }
+        Ok(Point {
+            x,
+            y,
+            origin: (x, y),
+            velocity,
+            acceleration,
+        })
     }
 }
 
-impl PartialEq for Point {
-    fn eq(&self, other: &Point) -> bool {
-        self.x == other.x && self.y == other.y
-    }
+/// Part of the sky to render, both ends included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: RangeInclusive<i32>,
+    pub y: RangeInclusive<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// plain PBM image, with the time in a comment
+    Pbm,
+    /// text like `Grid` prints it, under a line with the time
+    Text,
 }
 
 pub struct Grid {
     points: Vec<Point>,
-    x_range: Range<i32>,
-    y_range: Range<i32>,
+    time: i32,
+    x_range: RangeInclusive<i32>,
+    y_range: RangeInclusive<i32>,
 }
 
 impl Grid {
-    fn advance(&mut self) {
-        self.step_with(|p| p.advance());
+    fn new(points: Vec<Point>) -> Self {
+        let mut g = Grid {
+            points,
+            time: 0,
+            x_range: 0..=0,
+            y_range: 0..=0,
+        };
+        g.update_ranges();
+        g
     }
 
-    fn rewind(&mut self) {
-        self.step_with(|p| p.rewind());
+    /// Bounds of the points, both ends included.
+    fn update_ranges(&mut self) {
+        let (xs, ys) = (
+            self.points.iter().map(|p| p.x),
+            self.points.iter().map(|p| p.y),
+        );
+        self.x_range = xs.clone().min().unwrap_or(0)..=xs.max().unwrap_or(0);
+        self.y_range = ys.clone().min().unwrap_or(0)..=ys.max().unwrap_or(0);
     }
 
-    fn step_with<F>(&mut self, f: F)
-    where
-        F: Fn(&mut Point),
-    {
-        self.x_range = std::i32::MAX..std::i32::MIN;
-        self.y_range = std::i32::MAX..std::i32::MIN;
-        for p in self.points.iter_mut() {
-            f(p);
-            self.x_range = min(self.x_range.start, p.x)..max(self.x_range.end, p.x);
-            self.y_range = min(self.y_range.start, p.y)..max(self.y_range.end, p.y);
+    /// Seconds since the start.
+    pub fn time(&self) -> i32 {
+        self.time
+    }
+
+    /// Moves every star to where it is `t` seconds after the start, whether
+    /// that is in the future or in the past. Fails without moving anything
+    /// if a star would leave the `i32` range.
+    pub fn move_to(&mut self, t: i32) -> Result<(), Error> {
+        let positions = self
+            .points
+            .iter()
+            .map(|p| p.at(t))
+            .collect::<Option<Vec<(i32, i32)>>>()
+            .ok_or_else(|| format_err!("a star is out of range after {} seconds", t))?;
+        for (p, (x, y)) in self.points.iter_mut().zip(positions) {
+            p.x = x;
+            p.y = y;
         }
+        self.time = t;
+        self.update_ranges();
+        Ok(())
+    }
 
-        self.points.sort()
+    pub fn width(&self) -> i64 {
+        i64::from(*self.x_range.end()) - i64::from(*self.x_range.start()) + 1
     }
 
-    fn width(&self) -> i32 {
-        (self.x_range.end - self.x_range.start + 1)
+    pub fn height(&self) -> i64 {
+        i64::from(*self.y_range.end()) - i64::from(*self.y_range.start()) + 1
     }
 
-    fn height(&self) -> i32 {
-        (self.y_range.end - self.y_range.start + 1)
+    /// Positions of the stars.
+    pub fn stars(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.points.iter().map(|p| (p.x, p.y))
     }
 
-    fn converge(&mut self) -> usize {
-        let mut ret = 0;
-        let mut last_h = self.height();
-        while self.height() <= last_h {
-            last_h = self.height();
-            self.advance();
-            ret += 1;
+    fn viewport(&self) -> Viewport {
+        Viewport {
+            x: self.x_range.clone(),
+            y: self.y_range.clone(),
         }
-        self.rewind();
-        ret - 1
     }
-}
 
-impl fmt::Display for Grid {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let x_offset = self.x_range.start;
-        let y_offset = self.y_range.start;
-        let w = self.width();
+    fn pixels(&self, viewport: &Viewport) -> Vec<Vec<bool>> {
+        let len = |r: &RangeInclusive<i32>| {
+            max(i64::from(*r.end()) - i64::from(*r.start()) + 1, 0) as usize
+        };
+        let mut pixels = vec![vec![false; len(&viewport.x)]; len(&viewport.y)];
+        for p in self.points.iter() {
+            if viewport.x.contains(&p.x) && viewport.y.contains(&p.y) {
+                pixels[(p.y - viewport.y.start()) as usize][(p.x - viewport.x.start()) as usize] =
+                    true;
+            }
+        }
+        pixels
+    }
 
-        let mut y_last = 0;
-        let mut line: Vec<u8> = vec![b'.'; w as usize];
+    /// Time at which the points are the closest to their centre, in the
+    /// least squares sense. Positions are quadratic in time, so the sum of
+    /// squared distances is a quartic whose minimum is at a real root of its
+    /// derivative.
+    fn least_squares_time(&self) -> f64 {
+        let n = self.points.len() as f64;
+        let mean = |f: &dyn Fn(&Point) -> i32| {
+            self.points.iter().map(|p| f64::from(f(p))).sum::<f64>() / n
+        };
+        let centre = [
+            (
+                mean(&|p| p.origin.0),
+                mean(&|p| p.velocity.0),
+                mean(&|p| p.acceleration.0),
+            ),
+            (
+                mean(&|p| p.origin.1),
+                mean(&|p| p.velocity.1),
+                mean(&|p| p.acceleration.1),
+            ),
+        ];
+
+        // sum of squares of c0 + c1 t + c2 t^2, with c1 = v + a / 2 and
+        // c2 = a / 2 for each star and axis relative to the centre
+        let mut spread = [0.0; 5];
         for p in self.points.iter() {
-            let x = p.x - x_offset;
-            let y = p.y - y_offset;
-            if y != y_last {
-                writeln!(f, "{}", String::from_utf8(line).unwrap())?;
-                line = vec![b'.'; w as usize];
-                let nb_blank_lines = y - y_last - 1;
-                for _ in 0..nb_blank_lines {
-                    writeln!(f, "{}", ".".repeat(w as usize))?;
+            let axes = [
+                (p.origin.0, p.velocity.0, p.acceleration.0),
+                (p.origin.1, p.velocity.1, p.acceleration.1),
+            ];
+            for (&(x, v, a), &(cx, cv, ca)) in axes.iter().zip(centre.iter()) {
+                let a = f64::from(a) - ca;
+                let c = [f64::from(x) - cx, f64::from(v) - cv + a / 2.0, a / 2.0];
+                spread[0] += c[0] * c[0];
+                spread[1] += 2.0 * c[0] * c[1];
+                spread[2] += c[1] * c[1] + 2.0 * c[0] * c[2];
+                spread[3] += 2.0 * c[1] * c[2];
+                spread[4] += c[2] * c[2];
+            }
+        }
+
+        let derivative: Vec<f64> = (1..5).map(|i| i as f64 * spread[i]).collect();
+        let eval = |t: f64| spread.iter().rev().fold(0.0, |acc, c| acc * t + c);
+        real_roots(&derivative)
+            .into_iter()
+            .map(|t| t.round().max(0.0))
+            .chain(Some(0.0))
+            .min_by(|&a, &b| eval(a).partial_cmp(&eval(b)).unwrap())
+            .unwrap()
+    }
+
+    /// Moves the stars to the time found by `search`, and returns that time
+    /// with its score.
+    pub fn converge(&mut self, search: &Search) -> Result<(i32, i64), Error> {
+        let guess = self.least_squares_time() as i32;
+        let mut score_at = |t: i32| -> Result<i64, Error> {
+            self.move_to(t)?;
+            Ok(search.criterion.score(self))
+        };
+
+        let window = i32::try_from(search.window).unwrap_or(i32::MAX);
+        let (start, end) = (
+            max(guess.saturating_sub(window), 0),
+            guess.saturating_add(window),
+        );
+        let mut best = (start, score_at(start)?);
+        for t in start + 1..=end {
+            let score = score_at(t)?;
+            if score > best.1 {
+                best = (t, score);
+            }
+        }
+        // past the window, only follow the score while it improves
+        if best.0 == start {
+            while best.0 > 0 {
+                let score = score_at(best.0 - 1)?;
+                if score < best.1 {
+                    break;
                 }
+                best = (best.0 - 1, score);
             }
-            line[x as usize] = b'#';
-            y_last = y;
         }
-        writeln!(f, "{}", String::from_utf8(line).unwrap())
+        if best.0 == end {
+            loop {
+                let score = score_at(best.0 + 1)?;
+                if score <= best.1 {
+                    break;
+                }
+                best = (best.0 + 1, score);
+            }
+        }
+        self.move_to(best.0)?;
+        Ok(best)
     }
 }
 
-#[aoc_generator(day10)]
-pub fn input_generator(input: &str) -> Vec<RawPoint> {
-    let mut x_range = (0, 0);
-    let mut y_range = (0, 0);
-    let mut v: Vec<RawPoint> = input
-        .lines()
-        .map(|l| {
-            let v: Vec<&str> = l.trim().split(", ").collect();
-
-            let x: i32 = v[0]
-                .trim()
-                .split('<')
-                .last()
-                .unwrap()
-                .trim()
-                .parse()
-                .unwrap();
-
-            let v_y: i32 = v[2]
-                .trim()
-                .split('>')
-                .next()
-                .unwrap()
-                .trim()
-                .parse()
-                .unwrap();
-
-            let v: Vec<&str> = v[1].trim().split("> velocity=<").collect();
-            let y: i32 = v[0].trim().parse().unwrap();
-
-            let v_x: i32 = v[1].trim().parse().unwrap();
-            x_range = (min(x_range.0, x), max(x_range.1, x));
-            y_range = (min(y_range.0, y), max(y_range.1, y));
-            ((x, y), (v_x, v_y))
-        })
-        .collect();
-    v.push((x_range, y_range));
-    v
+/// Real roots of a polynomial given from its constant coefficient up. The
+/// roots of the derivative split it into monotonic pieces, each holding at
+/// most one root found by bisection.
+fn real_roots(poly: &[f64]) -> Vec<f64> {
+    let degree = match poly.iter().rposition(|&c| c != 0.0) {
+        Some(d) => d,
+        None => return vec![],
+    };
+    let poly = &poly[..=degree];
+    match degree {
+        0 => vec![],
+        1 => vec![-poly[0] / poly[1]],
+        _ => {
+            let eval = |t: f64| poly.iter().rev().fold(0.0, |acc, c| acc * t + c);
+            // every root is within this bound
+            let bound = 1.0
+                + poly[..degree]
+                    .iter()
+                    .map(|c| (c / poly[degree]).abs())
+                    .fold(0.0, f64::max);
+            let derivative: Vec<f64> = (1..=degree).map(|i| i as f64 * poly[i]).collect();
+            let mut ends = vec![-bound];
+            ends.extend(
+                real_roots(&derivative)
+                    .into_iter()
+                    .filter(|t| t.abs() < bound),
+            );
+            ends.push(bound);
+            ends.windows(2)
+                .filter_map(|w| {
+                    let (mut lo, mut hi) = (w[0], w[1]);
+                    if eval(lo).signum() * eval(hi).signum() > 0.0 {
+                        return None;
+                    }
+                    for _ in 0..100 {
+                        let mid = (lo + hi) / 2.0;
+                        if eval(mid).signum() * eval(lo).signum() > 0.0 {
+                            lo = mid;
+                        } else {
+                            hi = mid;
+                        }
+                    }
+                    Some((lo + hi) / 2.0)
+                })
+                .collect()
+        }
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.pixels(&self.viewport()) {
+            let line: String = row.iter().map(|&p| if p { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
 }
 
-fn build_grid(input: &[RawPoint]) -> Grid {
-    let mut points: Vec<Point> = input
+/// Letters of the font the messages are written with, 6 pixels wide and 10
+/// high.
+#[rustfmt::skip]
+const FONT: [(char, [&str; 10]); 15] = [
+    ('A', [
+        "..##..",
+        ".#..#.",
+        "#....#",
+        "#....#",
+        "#....#",
+        "######",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#....#",
+    ]),
+    ('B', [
+        "#####.",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#####.",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#####.",
+    ]),
+    ('C', [
+        ".####.",
+        "#....#",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#....#",
+        ".####.",
+    ]),
+    ('E', [
+        "######",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#####.",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "######",
+    ]),
+    ('F', [
+        "######",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#####.",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+    ]),
+    ('G', [
+        ".####.",
+        "#....#",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#..###",
+        "#....#",
+        "#....#",
+        "#...##",
+        ".###.#",
+    ]),
+    ('H', [
+        "#....#",
+        "#....#",
+        "#....#",
+        "#....#",
+        "######",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#....#",
+    ]),
+    ('J', [
+        "...###",
+        "....#.",
+        "....#.",
+        "....#.",
+        "....#.",
+        "....#.",
+        "....#.",
+        "#...#.",
+        "#...#.",
+        ".###..",
+    ]),
+    ('K', [
+        "#....#",
+        "#...#.",
+        "#..#..",
+        "#.#...",
+        "##....",
+        "##....",
+        "#.#...",
+        "#..#..",
+        "#...#.",
+        "#....#",
+    ]),
+    ('L', [
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "######",
+    ]),
+    ('N', [
+        "#....#",
+        "##...#",
+        "##...#",
+        "#.#..#",
+        "#.#..#",
+        "#..#.#",
+        "#..#.#",
+        "#...##",
+        "#...##",
+        "#....#",
+    ]),
+    ('P', [
+        "#####.",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#####.",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+        "#.....",
+    ]),
+    ('R', [
+        "#####.",
+        "#....#",
+        "#....#",
+        "#....#",
+        "#####.",
+        "#..#..",
+        "#...#.",
+        "#...#.",
+        "#....#",
+        "#....#",
+    ]),
+    ('X', [
+        "#....#",
+        "#....#",
+        ".#..#.",
+        ".#..#.",
+        "..##..",
+        "..##..",
+        ".#..#.",
+        ".#..#.",
+        "#....#",
+        "#....#",
+    ]),
+    ('Z', [
+        "######",
+        ".....#",
+        ".....#",
+        "....#.",
+        "...#..",
+        "..#...",
+        ".#....",
+        "#.....",
+        "#.....",
+        "######",
+    ]),
+];
+
+/// Reads a frame as printed by `Grid`, letters being separated by 2 blank
+/// columns.
+pub fn read_frame(frame: &str) -> Result<String, Error> {
+    let rows: Vec<&str> = frame.lines().collect();
+    if let Some(c) = rows
         .iter()
-        .take(input.len() - 1)
-        .map(|&i| Point::from(i))
-        .collect();
-    points.sort();
+        .flat_map(|r| r.chars())
+        .find(|&c| c != '#' && c != '.')
+    {
+        return Err(format_err!("unexpected {:?} in a frame", c));
+    }
+    let width = rows.first().map_or(0, |r| r.len());
+    if rows.len() != 10 || width % 8 != 6 || rows.iter().any(|r| r.len() != width) {
+        return Err(format_err!(
+            "a {}x{} frame isn't a line of letters",
+            width,
+            rows.len()
+        ));
+    }
+    (0..(width + 2) / 8)
+        .map(|i| {
+            let glyph: Vec<&str> = rows.iter().map(|r| &r[8 * i..8 * i + 6]).collect();
+            FONT.iter()
+                .find(|(_, g)| g[..] == glyph[..])
+                .map(|&(c, _)| c)
+                .ok_or_else(|| {
+                    format_err!("unknown letter at column {}:\n{}", 8 * i, glyph.join("\n"))
+                })
+        })
+        .collect()
+}
+
+/// The stars as seen at the beginning.
+#[derive(Debug)]
+pub struct Sky {
+    stars: Vec<Point>,
+}
+
+impl Sky {
+    /// The stars as they are `t` seconds after the start.
+    pub fn at(&self, t: i32) -> Result<Grid, Error> {
+        let mut g = build_grid(self);
+        g.move_to(t)?;
+        Ok(g)
+    }
+
+    pub fn len(&self) -> usize {
+        self.stars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stars.is_empty()
+    }
+}
+
+impl FromStr for Sky {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stars = s
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(i, l)| {
+                l.parse::<Point>()
+                    .map_err(|e| format_err!("line {}: {}", i + 1, e))
+            })
+            .collect::<Result<Vec<Point>, Error>>()?;
+        if stars.is_empty() {
+            return Err(format_err!("no stars in the sky"));
+        }
+        Ok(Sky { stars })
+    }
+}
+
+#[aoc_generator(day10)]
+pub fn input_generator(input: &str) -> Result<Sky, Error> {
+    input.parse()
+}
+
+fn build_grid(sky: &Sky) -> Grid {
+    Grid::new(sky.stars.clone())
+}
+
+/// Tells how readable the stars are at a given time.
+pub trait Criterion {
+    /// Higher scores are better.
+    fn score(&self, grid: &Grid) -> i64;
+}
+
+/// Smallest bounding box height.
+pub struct MinHeight;
 
-    let (x_range, y_range) = input.last().unwrap();
-    Grid {
-        points,
-        x_range: x_range.0..x_range.1,
-        y_range: y_range.0..y_range.1,
+impl Criterion for MinHeight {
+    fn score(&self, grid: &Grid) -> i64 {
+        -grid.height()
     }
 }
 
+/// Smallest bounding box area. The puzzle's messages have both the
+/// smallest height and the smallest area, but unlike the height the area
+/// keeps shrinking while stars close in on a row.
+pub struct MinArea;
+
+impl Criterion for MinArea {
+    fn score(&self, grid: &Grid) -> i64 {
+        -grid.width().saturating_mul(grid.height())
+    }
+}
+
+/// Most pairs of stars next to each other, horizontally or vertically.
+pub struct MaxAdjacency;
+
+impl Criterion for MaxAdjacency {
+    fn score(&self, grid: &Grid) -> i64 {
+        let stars: HashSet<(i32, i32)> = grid.stars().collect();
+        stars
+            .iter()
+            .map(|&(x, y)| stars.contains(&(x + 1, y)) as i64 + stars.contains(&(x, y + 1)) as i64)
+            .sum()
+    }
+}
+
+impl<F> Criterion for F
+where
+    F: Fn(&Grid) -> i64,
+{
+    fn score(&self, grid: &Grid) -> i64 {
+        self(grid)
+    }
+}
+
+/// How the time of the message is searched for. Every time from the start
+/// of the sky on and within `window` seconds of the least squares estimate
+/// is scored under `criterion`, ties going to the earliest. Past the edges
+/// of the window the search only goes on while the score improves, so a
+/// better time further away can be missed.
+#[derive(Clone, Copy)]
+pub struct Search<'a> {
+    pub criterion: &'a dyn Criterion,
+    pub window: u32,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(criterion: &'a dyn Criterion, window: u32) -> Self {
+        Search { criterion, window }
+    }
+}
+
+/// Walks down to the nearest smallest bounding box area, see `MinArea`.
+impl Default for Search<'_> {
+    fn default() -> Self {
+        Search::new(&MinArea, 0)
+    }
+}
+
+/// Best time of the message under `search`, and its score.
+pub fn best_time(sky: &Sky, search: &Search) -> Result<(i32, i64), Error> {
+    build_grid(sky).converge(search)
+}
+
+fn render(pixels: &[Vec<bool>], time: i32, format: FrameFormat) -> String {
+    let (on, off) = match format {
+        FrameFormat::Pbm => ('1', '0'),
+        FrameFormat::Text => ('#', '.'),
+    };
+    let mut frame = match format {
+        FrameFormat::Pbm => format!(
+            "P1\n# t={}\n{} {}\n",
+            time,
+            pixels.first().map_or(0, |r| r.len()),
+            pixels.len()
+        ),
+        FrameFormat::Text => format!("t={}\n", time),
+    };
+    // PBM lines are limited to 70 characters
+    let line = match format {
+        FrameFormat::Pbm => 70,
+        FrameFormat::Text => usize::MAX,
+    };
+    for row in pixels.iter() {
+        for chunk in row.chunks(line) {
+            frame.extend(chunk.iter().map(|&p| if p { on } else { off }));
+            frame.push('\n');
+        }
+    }
+    frame
+}
+
+/// Frames from `radius` seconds before the message found by `search` to
+/// `radius` seconds after, all showing the same part of the sky, the
+/// bounding box of the message by default. Concatenated, text frames make a
+/// flipbook, while plain PBM only allows one image per file.
+pub fn frames(
+    sky: &Sky,
+    search: &Search,
+    radius: i32,
+    viewport: Option<&Viewport>,
+    format: FrameFormat,
+) -> Result<Vec<(i32, String)>, Error> {
+    let mut g = build_grid(sky);
+    let (t, _) = g.converge(search)?;
+    let viewport = viewport.cloned().unwrap_or_else(|| g.viewport());
+    (t.saturating_sub(radius)..=t.saturating_add(radius))
+        .map(|time| {
+            g.move_to(time)?;
+            Ok((time, render(&g.pixels(&viewport), time, format)))
+        })
+        .collect()
+}
+
+/// Seconds until the message found by `search` appears, and the message.
+pub fn message(sky: &Sky, search: &Search) -> Result<(i32, String), Error> {
+    let mut g = build_grid(sky);
+    let (t, _) = g.converge(search)?;
+    Ok((t, g.to_string()))
+}
+
+/// The message as text.
+pub fn read_message(sky: &Sky, search: &Search) -> Result<String, Error> {
+    read_frame(&message(sky, search)?.1)
+}
+
 #[aoc(day10, part1)]
-pub fn solve_part1(input: &[RawPoint]) -> String {
-    let mut g = build_grid(input);
-    g.converge();
-    format!("\n{}", g)
+pub fn solve_part1(sky: &Sky) -> Result<String, Error> {
+    Ok(format!("\n{}", message(sky, &Search::default())?.1))
 }
 
 #[aoc(day10, part2)]
-pub fn solve_part2(input: &[RawPoint]) -> usize {
-    let mut g = build_grid(input);
-    g.converge()
+pub fn solve_part2(sky: &Sky) -> Result<i32, Error> {
+    Ok(message(sky, &Search::default())?.0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[test]
-    fn d10_part1() {
-        let input = "position=< 9,  1> velocity=< 0,  2>
+
+    const INPUT: &str = "position=< 9,  1> velocity=< 0,  2>
 position=< 7,  0> velocity=<-1,  0>
 position=< 3, -2> velocity=<-1,  1>
 position=< 6, 10> velocity=<-2, -1>
@@ -234,6 +802,25 @@ position=<-6,  0> velocity=< 2,  0>
 position=< 5,  9> velocity=< 1, -2>
 position=<14,  7> velocity=<-2,  0>
 position=<-3,  6> velocity=< 2, -1>";
+
+    /// One line of input per star of `word` written with the font, `star`
+    /// making the line from the position of the star in the message.
+    fn sky_spelling<F: Fn(i32, i32) -> String>(word: &str, star: F) -> String {
+        let mut input = String::new();
+        for (i, c) in word.chars().enumerate() {
+            let glyph = &FONT.iter().find(|(l, _)| *l == c).unwrap().1;
+            for (y, row) in glyph.iter().enumerate() {
+                for (x, _) in row.chars().enumerate().filter(|&(_, p)| p == '#') {
+                    input += &star((8 * i + x) as i32, y as i32);
+                    input.push('\n');
+                }
+            }
+        }
+        input
+    }
+
+    #[test]
+    fn d10_part1() {
         let res = "\n#...#..###
 #...#...#.
 #...#...#.
@@ -242,6 +829,212 @@ position=<-3,  6> velocity=< 2, -1>";
 #...#...#.
 #...#...#.
 #...#..###\n";
-        assert_eq!(solve_part1(&input_generator(input)), res);
+        assert_eq!(solve_part1(&input_generator(INPUT).unwrap()).unwrap(), res);
+    }
+
+    #[test]
+    fn d10_part2() {
+        assert_eq!(solve_part2(&input_generator(INPUT).unwrap()).unwrap(), 3);
+
+        // a pair of stars drifting together for a long time, with a third
+        // one that keeps the height constant until they meet
+        let input = "position=<-200000, 0> velocity=< 2, 0>
+position=< 200000, 1> velocity=<-2, 0>
+position=< 0, 5> velocity=< 0, 0>";
+        let (t, frame) = message(&input_generator(input).unwrap(), &Search::default()).unwrap();
+        assert_eq!(t, 100000);
+        assert_eq!(frame, "#\n#\n.\n.\n.\n#\n");
+    }
+
+    #[test]
+    fn d10_ocr() {
+        // still stars spelling a word, and a moving one that joins them
+        let mut input = sky_spelling("HAZEL", |x, y| {
+            format!("position=<{}, {}> velocity=<0, 0>", x, y)
+        });
+        input += "position=<32, 25> velocity=<0, -1>";
+        assert_eq!(
+            read_message(&input_generator(&input).unwrap(), &Search::default()).unwrap(),
+            "HAZEL"
+        );
+
+        // the example font is smaller
+        assert!(read_message(&input_generator(INPUT).unwrap(), &Search::default()).is_err());
+        let frame = "######\n".repeat(10);
+        let err = read_frame(&frame).unwrap_err().to_string();
+        assert!(err.starts_with("unknown letter at column 0"));
+        let frame = "#....#.é.....\n".repeat(10);
+        let err = read_frame(&frame).unwrap_err().to_string();
+        assert_eq!(err, "unexpected 'é' in a frame");
+    }
+
+    #[test]
+    fn d10_sky() {
+        let sky =
+            "position=<3,4>velocity=<+1,-2>\n\n  position = < 10 ,  20 >  velocity=< -1 , 0 >  \n";
+        let sky: Sky = sky.parse().unwrap();
+        assert_eq!(sky.len(), 2);
+
+        // every coordinate is positive
+        let g = build_grid(&sky);
+        assert_eq!((g.x_range.clone(), g.y_range.clone()), (3..=10, 4..=20));
+
+        let err = |s: &str| input_generator(s).unwrap_err().to_string();
+        assert_eq!(
+            err("position=<1, 2> velocity=<0, 0>\nposition=<1, 2> velocity=<0 0>"),
+            "line 2: expected \",\" at \"0>\""
+        );
+        assert_eq!(
+            err("position=<1, x> velocity=<0, 0>"),
+            "line 1: expected a number at \"x> velocity=<0, 0>\""
+        );
+        assert_eq!(
+            err("position=<1, 2> velocity=<0, 0> !"),
+            "line 1: unexpected \"!\""
+        );
+        assert_eq!(err("\n"), "no stars in the sky");
+    }
+
+    #[test]
+    fn d10_frames() {
+        let sky = input_generator(INPUT).unwrap();
+        let frames = frames(&sky, &Search::default(), 2, None, FrameFormat::Text).unwrap();
+        assert_eq!(
+            frames.iter().map(|f| f.0).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            frames[2].1,
+            format!("t=3\n{}", message(&sky, &Search::default()).unwrap().1)
+        );
+        for (_, frame) in frames.iter() {
+            assert_eq!(frame.lines().count(), 9);
+            assert!(frame.lines().skip(1).all(|l| l.len() == 10));
+        }
+
+        let sky: Sky = "position=<0, 0> velocity=<1, 0>
+position=<2, 2> velocity=<-1, 0>"
+            .parse()
+            .unwrap();
+        let viewport = Viewport { x: 0..=2, y: 0..=1 };
+        let frames = super::frames(
+            &sky,
+            &Search::default(),
+            1,
+            Some(&viewport),
+            FrameFormat::Pbm,
+        )
+        .unwrap();
+        assert_eq!(frames[0].1, "P1\n# t=0\n3 2\n100\n000\n");
+        assert_eq!(frames[2].1, "P1\n# t=2\n3 2\n001\n000\n");
+
+        // only PBM lines are wrapped
+        let sky: Sky = "position=<0, 0> velocity=<0, 0>\nposition=<99, 0> velocity=<0, 0>"
+            .parse()
+            .unwrap();
+        let text =
+            &super::frames(&sky, &Search::default(), 0, None, FrameFormat::Text).unwrap()[0].1;
+        assert_eq!(
+            text.lines().map(|l| l.len()).collect::<Vec<_>>(),
+            vec![3, 100]
+        );
+        let pbm = &super::frames(&sky, &Search::default(), 0, None, FrameFormat::Pbm).unwrap()[0].1;
+        assert_eq!(
+            pbm.lines().skip(3).map(|l| l.len()).collect::<Vec<_>>(),
+            vec![70, 30]
+        );
+    }
+
+    #[test]
+    fn d10_criteria() {
+        let sky = input_generator(INPUT).unwrap();
+        assert_eq!(
+            best_time(&sky, &Search::new(&MinHeight, 5)).unwrap(),
+            (3, -8)
+        );
+        assert_eq!(
+            best_time(&sky, &Search::new(&MinArea, 5)).unwrap(),
+            (3, -80)
+        );
+        assert_eq!(
+            best_time(&sky, &Search::new(&MaxAdjacency, 5)).unwrap().0,
+            3
+        );
+
+        // the stars line up vertically at 10 and horizontally at 20
+        let sky: Sky = "position=<-10, -40> velocity=< 1,  2>
+position=< 10,  40> velocity=<-1, -2>"
+            .parse()
+            .unwrap();
+        let narrowest = |g: &Grid| -g.width();
+        let criteria: [&dyn Criterion; 3] = [&MinHeight, &MinArea, &narrowest];
+        let times: Vec<i32> = criteria
+            .iter()
+            .map(|c| best_time(&sky, &Search::new(*c, 10)).unwrap().0)
+            .collect();
+        assert_eq!(times, vec![20, 20, 10]);
+        let search = Search::new(&narrowest, 10);
+        assert_eq!(message(&sky, &search).unwrap().0, 10);
+        assert_eq!(
+            frames(&sky, &search, 0, None, FrameFormat::Text).unwrap()[0].0,
+            10
+        );
+    }
+
+    #[test]
+    fn d10_acceleration() {
+        // a star stepped one second at a time lands where `at` puts it
+        let star: Point = "position=<3, -2> velocity=<1, 4> acceleration=<-2, 1>"
+            .parse()
+            .unwrap();
+        let (mut pos, mut vel) = ((3, -2), (1, 4));
+        for t in 1..20 {
+            vel = (vel.0 - 2, vel.1 + 1);
+            pos = (pos.0 + vel.0, pos.1 + vel.1);
+            assert_eq!(star.at(t), Some(pos));
+        }
+        assert_eq!(star.at(-1), Some((2, -6)));
+
+        // stars spelling a word 12 seconds after the start, some of them
+        // accelerating
+        let t = 12;
+        let input = sky_spelling("FAB", |x, y| {
+            let (v, a) = ((x % 5 - 2, y % 3 - 1), (y % 3 - 1, x % 2));
+            let start = |p: i32, v: i32, a: i32| p - v * t - a * t * (t + 1) / 2;
+            format!(
+                "position=<{}, {}> velocity=<{}, {}> acceleration=<{}, {}>",
+                start(x, v.0, a.0),
+                start(y, v.1, a.1),
+                v.0,
+                v.1,
+                a.0,
+                a.1
+            )
+        });
+        let sky = input_generator(&input).unwrap();
+        assert_eq!(message(&sky, &Search::default()).unwrap().0, 12);
+        assert_eq!(read_message(&sky, &Search::default()).unwrap(), "FAB");
+        assert_eq!(best_time(&sky, &Search::new(&MinHeight, 5)).unwrap().0, 12);
+
+        // going back and forth in time
+        let mut g = sky.at(40).unwrap();
+        assert_eq!(g.time(), 40);
+        g.move_to(12).unwrap();
+        assert_eq!(read_frame(&g.to_string()).unwrap(), "FAB");
+        g.move_to(-3).unwrap();
+        assert_eq!(g.to_string(), sky.at(-3).unwrap().to_string());
+
+        // out of range, whether the position or the computation overflows
+        let sky: Sky = "position=<0, 0> velocity=<0, 0> acceleration=<1000000, -2147483648>"
+            .parse()
+            .unwrap();
+        assert!(sky.at(10).is_err());
+        let mut g = sky.at(0).unwrap();
+        let err = g.move_to(i32::MAX).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a star is out of range after 2147483647 seconds"
+        );
+        assert_eq!(g.time(), 0);
     }
 }
@@ -1,32 +1,30 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
 use std::num::ParseIntError;
 use std::str::FromStr;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Area {
     Finite(usize),
     Infinite,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Point {
-    x: usize,
-    y: usize,
-    area: Area,
+    x: i64,
+    y: i64,
 }
 
 impl FromStr for Point {
     type Err = ParseIntError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let coords: Vec<&str> = s.split(", ").collect();
+        let coords: Vec<&str> = s.split(',').map(|c| c.trim()).collect();
 
         Ok(Self {
-            x: coords[0].parse::<usize>()?,
-            y: coords[1].parse::<usize>()?,
-            area: Area::Finite(0),
+            x: coords[0].parse::<i64>()?,
+            y: coords.get(1).unwrap_or(&"").parse::<i64>()?,
         })
     }
 }
@@ -36,102 +34,522 @@ pub fn input_generator(input: &str) -> Vec<Point> {
     input.lines().map(|l| l.trim().parse().unwrap()).collect()
 }
 
-fn get_grid_dim(input: &[Point]) -> (usize, usize, usize, usize) {
-    let ((min_x, min_y), (max_x, max_y)) =
-        input
-            .iter()
-            .fold(((std::usize::MAX, std::usize::MAX), (0, 0)), |acc, p| {
-                let ((mut min_x, mut min_y), (mut max_x, mut max_y)) = acc;
-                if p.x < min_x {
-                    min_x = p.x;
-                } else if p.x > max_x {
-                    max_x = p.x;
-                }
+fn get_bounding_box(input: &[Point]) -> (i64, i64, i64, i64) {
+    input.iter().fold(
+        (i64::MAX, i64::MAX, i64::MIN, i64::MIN),
+        |(min_x, min_y, max_x, max_y), p| {
+            (
+                min_x.min(p.x),
+                min_y.min(p.y),
+                max_x.max(p.x),
+                max_y.max(p.y),
+            )
+        },
+    )
+}
 
-                if p.y < min_y {
-                    min_y = p.y;
-                } else if p.y > max_y {
-                    max_y = p.y;
-                }
+/// A value changing by `slope` at each row of a sweep.
+#[derive(Clone, Copy, Debug)]
+struct Linear {
+    value: i64,
+    slope: i64,
+}
 
-                ((min_x, min_y), (max_x, max_y))
-            });
-    let width = max_x - min_x;
-    let height = max_y - min_y;
+impl Linear {
+    fn constant(value: i64) -> Self {
+        Linear { value, slope: 0 }
+    }
+
+    fn add(self, other: Linear) -> Self {
+        Linear {
+            value: self.value + other.value,
+            slope: self.slope + other.slope,
+        }
+    }
+
+    fn sub(self, other: Linear) -> Self {
+        Linear {
+            value: self.value - other.value,
+            slope: self.slope - other.slope,
+        }
+    }
+
+    /// Halves a value whose slope is even, rounding down.
+    fn half(self) -> Self {
+        Linear {
+            value: self.value.div_euclid(2),
+            slope: self.slope / 2,
+        }
+    }
+
+    fn at(self, row: i64) -> i64 {
+        self.value + self.slope * row
+    }
+}
 
-    (width, height, min_x, min_y)
+/// Compares `a` and `b` on the current row, and shortens `rows` to the
+/// number of rows over which the result stays the same.
+fn compare(a: Linear, b: Linear, rows: &mut i64) -> Ordering {
+    let d = a.sub(b);
+    if d.slope != 0 && (d.value == 0 || d.value.signum() != d.slope.signum()) {
+        // `d` reaches zero or changes sign after `|value| / |slope|` rows
+        let stable = (d.value.abs() + d.slope.abs() - 1) / d.slope.abs();
+        *rows = (*rows).min(stable.max(1));
+    }
+    d.value.cmp(&0)
 }
 
-#[aoc(day6, part1)]
-pub fn solve_part1(input: &[Point]) -> usize {
-    let (width, height, offset_x, offset_y) = get_grid_dim(input);
-
-    let mut grid = vec![(std::usize::MAX, std::usize::MAX); width * height];
-    for (i, p) in input.iter().enumerate() {
-        let px = (p.x - offset_x) as i64;
-        let py = (p.y - offset_y) as i64;
-        for (j, e) in grid.iter_mut().enumerate() {
-            let gx = (j % width) as i64;
-            let gy = (j / width) as i64;
-            let d = ((px - gx).abs() + (py - gy).abs()) as usize;
-            if e.1 > d {
-                e.0 = i as usize;
-                e.1 = d;
-            } else if e.1 == d {
-                e.0 = std::usize::MAX;
+fn min_of(a: Linear, b: Linear, rows: &mut i64) -> Linear {
+    if compare(a, b, rows) == Ordering::Greater {
+        b
+    } else {
+        a
+    }
+}
+
+fn max_of(a: Linear, b: Linear, rows: &mut i64) -> Linear {
+    if compare(a, b, rows) == Ordering::Less {
+        b
+    } else {
+        a
+    }
+}
+
+/// Nearest points of a group, `count` being the number of points tied at
+/// `distance` and `owner` one of them.
+#[derive(Clone, Copy, Debug)]
+struct Closest {
+    distance: Linear,
+    count: usize,
+    owner: usize,
+}
+
+impl Closest {
+    const NONE: Closest = Closest {
+        distance: Linear {
+            value: i64::MAX,
+            slope: 0,
+        },
+        count: 0,
+        owner: usize::MAX,
+    };
+
+    fn merge(self, other: Closest, rows: &mut i64) -> Closest {
+        if self.count == 0 {
+            return other;
+        } else if other.count == 0 {
+            return self;
+        }
+        match compare(self.distance, other.distance, rows) {
+            Ordering::Less => self,
+            Ordering::Greater => other,
+            Ordering::Equal => Closest {
+                count: self.count + other.count,
+                ..self
+            },
+        }
+    }
+
+    fn shift(self, offset: i64) -> Closest {
+        if self.count == 0 {
+            self
+        } else {
+            Closest {
+                distance: self.distance.add(Linear::constant(offset)),
+                ..self
             }
         }
     }
-    let mut h = HashMap::new();
-    for (i, e) in grid.iter_mut().enumerate() {
-        if e.0 == std::usize::MAX {
+
+    fn owner(&self) -> Option<usize> {
+        if self.count == 1 {
+            Some(self.owner)
+        } else {
+            None
+        }
+    }
+}
+
+/// Indices of the points, grouped by column and sorted by `x`.
+fn get_columns(input: &[Point]) -> Vec<(i64, Vec<usize>)> {
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_by_key(|&i| input[i].x);
+
+    let mut columns: Vec<(i64, Vec<usize>)> = vec![];
+    for i in order {
+        match columns.last_mut() {
+            Some((x, members)) if *x == input[i].x => members.push(i),
+            _ => columns.push((input[i].x, vec![i])),
+        }
+    }
+    columns
+}
+
+/// A run of cells `start..=end` on a single row, owned by a point or by no
+/// one when several points are at the same distance.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    start: Linear,
+    end: Linear,
+    owner: Option<usize>,
+}
+
+/// Splits row `y` between the first and the last column into spans, and
+/// shortens `rows` to the number of rows from `y` sharing the same spans up
+/// to their ends moving by a fixed step per row. No point may lie strictly
+/// between `y` and `y + rows`.
+///
+/// Between two consecutive columns, the distance to every point on the left
+/// grows by one per cell and the distance to every point on the right
+/// shrinks by one, so each gap is cut in at most three spans and the cost of
+/// a row only depends on the number of columns, not on its width.
+fn get_row_spans(
+    input: &[Point],
+    columns: &[(i64, Vec<usize>)],
+    y: i64,
+    rows: &mut i64,
+) -> Vec<Span> {
+    let column_best: Vec<Closest> = columns
+        .iter()
+        .map(|(_, members)| {
+            members
+                .iter()
+                .map(|&i| Closest {
+                    distance: Linear {
+                        value: (input[i].y - y).abs(),
+                        slope: if input[i].y <= y { 1 } else { -1 },
+                    },
+                    count: 1,
+                    owner: i,
+                })
+                .fold(Closest::NONE, |a, b| a.merge(b, rows))
+        })
+        .collect();
+
+    // distance to a point on the left of `x` is `left + x`, on the right `right - x`
+    let mut left = Vec::with_capacity(columns.len());
+    let mut acc = Closest::NONE;
+    for ((x, _), best) in columns.iter().zip(column_best.iter()) {
+        acc = acc.merge(best.shift(-x), rows);
+        left.push(acc);
+    }
+    let mut right = vec![Closest::NONE; columns.len()];
+    let mut acc = Closest::NONE;
+    for (k, ((x, _), best)) in columns.iter().zip(column_best.iter()).enumerate().rev() {
+        acc = acc.merge(best.shift(*x), rows);
+        right[k] = acc;
+    }
+
+    let mut spans = vec![];
+    for (k, (x, _)) in columns.iter().enumerate() {
+        let mut here = column_best[k];
+        if k > 0 {
+            here = here.merge(left[k - 1].shift(*x), rows);
+        }
+        if k + 1 < columns.len() {
+            here = here.merge(right[k + 1].shift(-x), rows);
+        }
+        spans.push(Span {
+            start: Linear::constant(*x),
+            end: Linear::constant(*x),
+            owner: here.owner(),
+        });
+
+        if k + 1 == columns.len() || columns[k + 1].0 - x <= 1 {
             continue;
         }
-        let p = h.entry(e.0).or_insert_with(|| input[e.0].clone());
-        if i % width == 0 || i < width || i % width == width - 1 || i > width * (height - 1) {
-            p.area = Area::Infinite;
-        } else if let Area::Finite(cpt) = p.area {
-            p.area = Area::Finite(cpt + 1);
+        let (start, end) = (
+            Linear::constant(x + 1),
+            Linear::constant(columns[k + 1].0 - 1),
+        );
+        let (l, r) = (left[k], right[k + 1]);
+        // left wins while `l + x < r - x`; the slope of `split` is even so
+        // its parity is the same on every row
+        let split = r.distance.sub(l.distance);
+        let one = Linear::constant(1);
+        let last_left = min_of(split.sub(one).half(), end, rows);
+        let first_right = max_of(split.half().add(one), start, rows);
+        if compare(last_left, start, rows) != Ordering::Less {
+            spans.push(Span {
+                start,
+                end: last_left,
+                owner: l.owner(),
+            });
+        }
+        let middle = split.half();
+        if split.value % 2 == 0
+            && compare(middle, start, rows) != Ordering::Less
+            && compare(middle, end, rows) != Ordering::Greater
+        {
+            spans.push(Span {
+                start: middle,
+                end: middle,
+                owner: None,
+            });
+        }
+        if compare(first_right, end, rows) != Ordering::Greater {
+            spans.push(Span {
+                start: first_right,
+                end,
+                owner: r.owner(),
+            });
         }
     }
+    spans
+}
+
+fn get_areas(input: &[Point]) -> Vec<Area> {
+    let (min_x, min_y, max_x, max_y) = get_bounding_box(input);
+    let mut ys: Vec<i64> = input.iter().map(|p| p.y).collect();
+    ys.sort();
+    ys.dedup();
 
-    let a = h
+    // rows are swept in runs where every span grows or shrinks by a fixed
+    // step, so the empty space between the points costs nothing
+    let columns = get_columns(input);
+    let mut areas = vec![Area::Finite(0); input.len()];
+    let mut y = min_y;
+    while y <= max_y {
+        let border = y == min_y || y == max_y;
+        let next = ys[ys.partition_point(|&v| v <= y).min(ys.len() - 1)];
+        let mut rows = if border { 1 } else { next - y };
+        let spans = get_row_spans(input, &columns, y, &mut rows);
+        for span in spans {
+            if let Some(i) = span.owner {
+                // owning a cell on the border means owning everything beyond it
+                if border || span.start.value == min_x || span.end.value == max_x {
+                    areas[i] = Area::Infinite;
+                } else if let Area::Finite(a) = areas[i] {
+                    let first = span.end.value - span.start.value + 1;
+                    let last = span.end.at(rows - 1) - span.start.at(rows - 1) + 1;
+                    let cells = i128::from(first + last) * i128::from(rows) / 2;
+                    areas[i] = Area::Finite(a + cells as usize);
+                }
+            }
+        }
+        y += rows;
+    }
+    areas
+}
+
+#[aoc(day6, part1)]
+pub fn solve_part1(input: &[Point]) -> usize {
+    if input.is_empty() {
+        return 0;
+    }
+
+    get_areas(input)
         .into_iter()
-        .max_by(|(_, a), (_, b)| match a.area {
-            Area::Infinite => std::cmp::Ordering::Less,
-            Area::Finite(a) => match b.area {
-                Area::Infinite => std::cmp::Ordering::Greater,
-                Area::Finite(b) => a.cmp(&b),
-            },
+        .filter_map(|a| match a {
+            Area::Finite(a) => Some(a),
+            Area::Infinite => None,
         })
-        .unwrap()
-        .1
-        .area;
+        .max()
+        .unwrap_or(0)
+}
 
-    if let Area::Finite(a) = a {
-        a
-    } else {
-        0
+/// Coordinates of the points along one axis, sorted with their prefix sums
+/// so the sum of distances to any coordinate is a binary search away.
+#[derive(Clone, Debug)]
+struct Axis {
+    coords: Vec<i64>,
+    prefix: Vec<i64>,
+}
+
+impl Axis {
+    fn new(mut coords: Vec<i64>) -> Self {
+        coords.sort();
+        let mut prefix = vec![0];
+        for c in coords.iter() {
+            prefix.push(prefix[prefix.len() - 1] + c);
+        }
+        Self { coords, prefix }
+    }
+
+    fn distance_sum(&self, c: i64) -> i64 {
+        let n = self.coords.len();
+        let k = self.coords.partition_point(|&p| p <= c);
+        let (below, above) = (self.prefix[k], self.prefix[n] - self.prefix[k]);
+        c * k as i64 - below + above - c * (n - k) as i64
+    }
+
+    fn median(&self) -> i64 {
+        self.coords[self.coords.len() / 2]
+    }
+
+    fn min_distance_sum(&self) -> i64 {
+        self.distance_sum(self.median())
+    }
+
+    /// Sorted sums of distances for every coordinate where it is below `limit`.
+    fn distance_sums_below(&self, limit: i64) -> Vec<i64> {
+        // the sum is convex and smallest at the median, so these coordinates
+        // are a single run around it, no longer than `limit`
+        let median = self.median();
+        let mut sums: Vec<i64> = (0..)
+            .map(|k| self.distance_sum(median - k))
+            .take_while(|&s| s < limit)
+            .collect();
+        sums.extend(
+            (1..)
+                .map(|k| self.distance_sum(median + k))
+                .take_while(|&s| s < limit),
+        );
+        sums.sort();
+        sums
     }
 }
 
-#[aoc(day6, part2)]
-pub fn solve_part2(input: &[Point]) -> usize {
-    let (width, height, offset_x, offset_y) = get_grid_dim(input);
-    let mut grid = vec![0; width * height];
-    for (i, e) in grid.iter_mut().enumerate() {
-        let gx = (i % width) as i64;
-        let gy = (i / width) as i64;
-        for p in input {
-            let px = (p.x - offset_x) as i64;
-            let py = (p.y - offset_y) as i64;
-            let d = ((px - gx).abs() + (py - gy).abs()) as usize;
-            *e += d;
-            if *e >= 10000 {
-                break;
+/// Per-query answers about the plane split between a list of points: the
+/// areas are computed once, ownership and safety are evaluated on demand for
+/// any cell, inside the bounding box or not.
+#[derive(Clone, Debug)]
+pub struct Territory {
+    points: Vec<Point>,
+    areas: Vec<Area>,
+    xs: Axis,
+    ys: Axis,
+    limit: i64,
+}
+
+impl Territory {
+    /// `limit` is the total distance under which a cell is safe. `None` if
+    /// there are no points.
+    pub fn new(points: &[Point], limit: i64) -> Option<Self> {
+        if points.is_empty() {
+            return None;
+        }
+        Some(Self {
+            points: points.to_vec(),
+            areas: get_areas(points),
+            xs: Axis::new(points.iter().map(|p| p.x).collect()),
+            ys: Axis::new(points.iter().map(|p| p.y).collect()),
+            limit,
+        })
+    }
+
+    /// Index of the point closest to `(x, y)`, `None` on a tie.
+    pub fn owner(&self, x: i64, y: i64) -> Option<usize> {
+        self.points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Closest {
+                distance: Linear::constant((p.x - x).abs() + (p.y - y).abs()),
+                count: 1,
+                owner: i,
+            })
+            .fold(Closest::NONE, |a, b| a.merge(b, &mut 1))
+            .owner()
+    }
+
+    pub fn total_distance(&self, x: i64, y: i64) -> i64 {
+        self.xs.distance_sum(x) + self.ys.distance_sum(y)
+    }
+
+    pub fn is_safe(&self, x: i64, y: i64) -> bool {
+        self.total_distance(x, y) < self.limit
+    }
+
+    /// Area owned by point `i`, `None` if there is no such point.
+    pub fn area(&self, i: usize) -> Option<Area> {
+        self.areas.get(i).cloned()
+    }
+
+    pub fn areas(&self) -> &[Area] {
+        &self.areas
+    }
+
+    /// Number of safe cells.
+    pub fn safe_area(&self) -> usize {
+        // the total distance is the sum of a term in x and a term in y, so the
+        // region is counted from both axes without visiting its cells; each
+        // axis only needs the sums leaving room for the best of the other
+        let sx = self
+            .xs
+            .distance_sums_below(self.limit - self.ys.min_distance_sum());
+        let sy = self
+            .ys
+            .distance_sums_below(self.limit - self.xs.min_distance_sum());
+
+        let mut count = 0;
+        let mut j = sy.len();
+        for dx in sx {
+            while j > 0 && dx + sy[j - 1] >= self.limit {
+                j -= 1;
             }
+            count += j;
         }
+        count
     }
+}
 
-    grid.into_iter().filter(|x| *x < 10000).count()
+pub fn generic_solve_part2(input: &[Point], limit: i64) -> usize {
+    Territory::new(input, limit).map_or(0, |t| t.safe_area())
+}
+
+#[aoc(day6, part2)]
+pub fn solve_part2(input: &[Point]) -> usize {
+    generic_solve_part2(input, 10000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9";
+
+    #[test]
+    fn d6_part1() {
+        assert_eq!(solve_part1(&input_generator(INPUT)), 17);
+    }
+
+    #[test]
+    fn d6_part2() {
+        assert_eq!(generic_solve_part2(&input_generator(INPUT), 32), 16);
+    }
+
+    #[test]
+    fn d6_signed_and_sparse() {
+        let negated = "-1, -1\n-1, -6\n-8, -3\n-3, -4\n-5, -5\n-8, -9";
+        assert_eq!(solve_part1(&input_generator(negated)), 17);
+
+        let shifted: String = input_generator(INPUT)
+            .iter()
+            .map(|p| format!("{}, {}\n", p.x - 1_000_000_000_000, p.y + 1_000_000_000))
+            .collect();
+        let input = input_generator(&shifted);
+        assert_eq!(solve_part1(&input), 17);
+        assert_eq!(generic_solve_part2(&input, 32), 16);
+    }
+
+    #[test]
+    fn d6_spread() {
+        let input = input_generator("0, 0\n1000000000, 1000000000\n500000000, 3");
+        assert_eq!(solve_part1(&input), 0);
+        assert_eq!(generic_solve_part2(&input, 10000), 0);
+
+        // the center owns the square of cells closer to it than to any corner
+        let k = 1_000_000_000;
+        let diamond = format!("0, 0\n{0}, 0\n-{0}, 0\n0, {0}\n0, -{0}", 2 * k);
+        let input = input_generator(&diamond);
+        assert_eq!(solve_part1(&input), ((2 * k - 1) * (2 * k - 1)) as usize);
+        assert_eq!(generic_solve_part2(&input, 8 * k + 5), 5);
+    }
+
+    #[test]
+    fn d6_territory() {
+        assert!(Territory::new(&[], 32).is_none());
+        let territory = Territory::new(&input_generator(INPUT), 32).unwrap();
+        assert_eq!(territory.owner(5, 2), Some(4));
+        assert_eq!(territory.owner(0, 4), None);
+        assert_eq!(territory.owner(-1000, -1000), Some(0));
+        assert_eq!(territory.owner(1000, 5), Some(2));
+        assert_eq!(territory.area(3), Some(Area::Finite(9)));
+        assert_eq!(territory.area(4), Some(Area::Finite(17)));
+        assert_eq!(territory.area(0), Some(Area::Infinite));
+        assert_eq!(territory.area(6), None);
+        assert_eq!(territory.total_distance(4, 3), 30);
+        assert!(territory.is_safe(4, 3));
+        assert!(!territory.is_safe(-1000, 4));
+    }
 }
@@ -1,156 +1,1242 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use failure::{bail, format_err, Error};
 
 #[derive(Debug, Clone)]
 pub struct Step {
-    id: char,
-    allows: Vec<char>,
-    blocked_by: Vec<char>,
+    id: String,
+    allows: Vec<String>,
+    blocked_by: Vec<String>,
     has_dependencies: bool,
 }
 
 impl Step {
-    fn get_time(&self, penalty: usize) -> usize {
-        penalty + 1 + self.id as usize - 'A' as usize
+    fn new(id: &str) -> Self {
+        Step {
+            id: id.to_owned(),
+            allows: vec![],
+            blocked_by: vec![],
+            has_dependencies: false,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Steps waiting on this one.
+    pub fn allows(&self) -> &[String] {
+        &self.allows
+    }
+
+    /// Steps this one waits on.
+    pub fn blocked_by(&self) -> &[String] {
+        &self.blocked_by
+    }
+
+    fn get_time<F>(&self, duration: &F) -> Result<usize, Error>
+    where
+        F: Fn(&str) -> Option<usize>,
+    {
+        match duration(&self.id) {
+            Some(0) => Err(format_err!(
+                "step {} must last at least one second",
+                self.id
+            )),
+            Some(t) => Ok(t),
+            None => Err(format_err!("no duration for step {}", self.id)),
+        }
     }
 }
 
-#[aoc_generator(day7)]
-pub fn input_generator(input: &str) -> Vec<(char, char)> {
-    input
+/// Duration of a step named after a single uppercase letter: `penalty` plus
+/// the rank of the letter in the alphabet.
+pub fn letter_duration(id: &str, penalty: usize) -> Option<usize> {
+    let mut chars = id.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Some(penalty + 1 + c as usize - 'A' as usize),
+        _ => None,
+    }
+}
+
+/// Steps and the dependencies between them, as read from the puzzle input
+/// or one of the alternative formats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Instructions {
+    /// steps declared on their own, possibly without any dependency
+    steps: Vec<String>,
+    /// `(dependency, depends)` pairs, `depends` waiting on `dependency`
+    edges: Vec<(String, String)>,
+}
+
+impl From<Vec<(String, String)>> for Instructions {
+    fn from(edges: Vec<(String, String)>) -> Self {
+        Instructions {
+            steps: vec![],
+            edges,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// `Step A must be finished before step B can begin.`
+    Sentences,
+    /// `A -> B`, or a lone `A` for a step without dependency
+    EdgeList,
+    /// `{ "B": ["A"], "A": [] }`, every step being a key
+    Json,
+}
+
+impl Format {
+    pub fn detect(input: &str) -> Format {
+        let first = input.lines().map(|l| l.trim()).find(|l| !l.is_empty());
+        match first {
+            Some(l) if l.starts_with('{') => Format::Json,
+            Some(l) if l.starts_with("Step ") && l.contains(" must be finished before step ") => {
+                Format::Sentences
+            }
+            _ => Format::EdgeList,
+        }
+    }
+}
+
+impl FromStr for Instructions {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Instructions::parse(s, Format::detect(s))
+    }
+}
+
+impl Instructions {
+    pub fn parse(input: &str, format: Format) -> Result<Self, Error> {
+        match format {
+            Format::Sentences => parse_sentences(input),
+            Format::EdgeList => parse_edge_list(input),
+            Format::Json => JsonParser::new(input).parse(),
+        }
+    }
+}
+
+fn parse_sentences(input: &str) -> Result<Instructions, Error> {
+    let mut edges = vec![];
+    for (n, l) in input
         .lines()
-        .map(|l| {
-            let v: Vec<&str> = l.trim().split(" must be finished before step ").collect();
-            (v[0].chars().last().unwrap(), v[1].chars().next().unwrap())
-        })
-        .collect()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let v: Vec<&str> = l.trim().split(" must be finished before step ").collect();
+        match (v.first(), v.get(1), v.len()) {
+            (Some(a), Some(b), 2) if a.starts_with("Step ") && b.ends_with(" can begin.") => {
+                edges.push((
+                    a.trim_start_matches("Step ").to_owned(),
+                    b.trim_end_matches(" can begin.").to_owned(),
+                ));
+            }
+            _ => bail!(
+                "line {}: expected \"Step A must be finished before step B can begin.\"",
+                n + 1
+            ),
+        }
+    }
+    Ok(edges.into())
 }
 
-fn build_graph(input: &[(char, char)]) -> HashMap<char, Step> {
-    let mut h: HashMap<char, Step> = HashMap::new();
-    input.iter().for_each(|(dependency, depends)| {
-        let a = h.entry(*dependency).or_insert(Step {
-            id: *dependency,
-            allows: vec![],
-            blocked_by: vec![],
-            has_dependencies: false,
-        });
-        a.allows.push(*depends);
+fn parse_edge_list(input: &str) -> Result<Instructions, Error> {
+    let mut instructions = Instructions::default();
+    for (n, l) in input
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let v: Vec<&str> = l.split("->").map(|s| s.trim()).collect();
+        if v.iter().any(|s| s.is_empty()) {
+            bail!("line {}: expected \"A -> B\" or \"A\"", n + 1);
+        }
+        match v.len() {
+            1 => instructions.steps.push(v[0].to_owned()),
+            2 => instructions.edges.push((v[0].to_owned(), v[1].to_owned())),
+            _ => bail!("line {}: expected \"A -> B\" or \"A\"", n + 1),
+        }
+    }
+    Ok(instructions)
+}
+
+/// Reader for the subset of JSON used to describe steps: a single object
+/// mapping each step to the array of steps it waits on.
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(input: &'a str) -> Self {
+        JsonParser {
+            chars: input.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, expected: &str) -> Error {
+        format_err!("line {}: expected {}", self.line, expected)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.chars.next();
+        }
+    }
+
+    /// Consumes `c` if it is the next significant character.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", c)))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect('"')
+            .map_err(|_| self.error("a double quoted string"))?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = self.chars.by_ref().take(4).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| self.error("a valid unicode escape"))?;
+                        s.push(c);
+                    }
+                    Some(c) if c == '"' || c == '\\' || c == '/' => s.push(c),
+                    _ => return Err(self.error("a valid escape sequence")),
+                },
+                Some('\n') | None => return Err(self.error("a closing '\"'")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Parses a comma separated sequence closed by `close`, the opening
+    /// delimiter being already consumed.
+    fn sequence<T, G>(&mut self, close: char, mut item: G) -> Result<Vec<T>, Error>
+    where
+        G: FnMut(&mut Self) -> Result<T, Error>,
+    {
+        let mut items = vec![];
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(',')
+                .map_err(|_| self.error(&format!("',' or '{}'", close)))?;
+        }
+    }
+
+    fn parse(mut self) -> Result<Instructions, Error> {
+        self.expect('{')?;
+        let entries = self.sequence('}', |p| {
+            let line = p.line;
+            let step = p.string()?;
+            p.expect(':')?;
+            p.expect('[')?;
+            let dependencies = p.sequence(']', |p| Ok((p.line, p.string()?)))?;
+            Ok((line, step, dependencies))
+        })?;
+        self.skip_whitespace();
+        if self.chars.peek().is_some() {
+            return Err(self.error("the end of the document"));
+        }
+
+        let mut instructions = Instructions::default();
+        let mut known = HashSet::new();
+        for (line, step, _) in entries.iter() {
+            if !known.insert(step.as_str()) {
+                bail!("line {}: step {} is declared twice", line, step);
+            }
+        }
+        for (_, step, dependencies) in entries.iter() {
+            for (line, dependency) in dependencies.iter() {
+                if !known.contains(dependency.as_str()) {
+                    bail!(
+                        "line {}: unknown step {} referenced by step {}",
+                        line,
+                        dependency,
+                        step
+                    );
+                }
+                instructions.edges.push((dependency.clone(), step.clone()));
+            }
+            instructions.steps.push(step.clone());
+        }
+        Ok(instructions)
+    }
+}
+
+#[aoc_generator(day7)]
+pub fn input_generator(input: &str) -> Result<Instructions, Error> {
+    input.parse()
+}
+
+fn build_graph(input: &Instructions) -> Result<HashMap<String, Step>, Error> {
+    let mut h: HashMap<String, Step> = input
+        .steps
+        .iter()
+        .map(|id| (id.clone(), Step::new(id)))
+        .collect();
+    input.edges.iter().for_each(|(dependency, depends)| {
+        let a = h
+            .entry(dependency.clone())
+            .or_insert_with(|| Step::new(dependency));
+        // a repeated instruction adds nothing
+        if a.allows.contains(depends) {
+            return;
+        }
+        a.allows.push(depends.clone());
         a.allows.sort();
 
-        let d = h.entry(*depends).or_insert(Step {
-            id: *depends,
-            allows: vec![],
-            blocked_by: vec![],
-            has_dependencies: false,
-        });
+        let d = h
+            .entry(depends.clone())
+            .or_insert_with(|| Step::new(depends));
         d.has_dependencies = true;
-        d.blocked_by.push(*dependency);
+        d.blocked_by.push(dependency.clone());
     });
-    h
+    validate_graph(&h)?;
+    Ok(h)
 }
 
-fn find_standalone_tasks(tasks: &HashMap<char, Step>) -> Vec<Step> {
-    let mut ready: Vec<Step> = tasks
-        .iter()
-        .filter(|(_, s)| !s.has_dependencies)
-        .map(|(_, s)| s.clone())
+/// Checks that the steps can all be completed, i.e. that there is no
+/// dependency cycle.
+fn validate_graph(tasks: &HashMap<String, Step>) -> Result<(), Error> {
+    // peel off steps without remaining blockers, whatever is left is either
+    // in a cycle or waiting on one
+    let mut remaining: HashSet<&str> = tasks.keys().map(|id| id.as_str()).collect();
+    let mut blockers: HashMap<&str, usize> = tasks
+        .values()
+        .map(|s| (s.id.as_str(), s.blocked_by.len()))
+        .collect();
+    let mut ready: Vec<&str> = tasks
+        .values()
+        .filter(|s| !s.has_dependencies)
+        .map(|s| s.id.as_str())
         .collect();
-    ready.sort_by(|a, b| b.id.cmp(&a.id));
-    ready
+    while let Some(id) = ready.pop() {
+        remaining.remove(id);
+        for next in tasks[id].allows.iter() {
+            let count = blockers.get_mut(next.as_str()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push(next);
+            }
+        }
+    }
+
+    if let Some(&start) = remaining.iter().min() {
+        // every remaining step has a remaining blocker, walking back through
+        // them always ends up looping
+        let mut path = vec![start];
+        loop {
+            let current = path[path.len() - 1];
+            let blocker = tasks[current]
+                .blocked_by
+                .iter()
+                .map(|b| b.as_str())
+                .filter(|b| remaining.contains(b))
+                .min()
+                .unwrap();
+            if let Some(pos) = path.iter().position(|&id| id == blocker) {
+                let mut cycle = path.split_off(pos);
+                cycle.push(blocker);
+                cycle.reverse();
+                bail!("dependency cycle between steps {}", cycle.join(" -> "));
+            }
+            path.push(blocker);
+        }
+    }
+
+    Ok(())
 }
 
-fn unlock_tasks(completed: &Step, tasks: &HashMap<char, Step>, ready: &mut Vec<Step>) {
-    'outer: for s in completed.allows.iter().map(|id| &tasks[&id]) {
-        for blocker in s.blocked_by.iter() {
-            if tasks.contains_key(blocker) {
-                continue 'outer;
+/// Decides which of the ready steps is taken first.
+pub trait Priority {
+    /// Rank of a ready step: higher ranks are taken first, equal ranks in
+    /// alphabetical order.
+    fn rank(&self, step: &Step) -> i64;
+}
+
+/// Ready steps in alphabetical order, as in the puzzle.
+pub struct Alphabetical;
+
+impl Priority for Alphabetical {
+    fn rank(&self, _: &Step) -> i64 {
+        0
+    }
+}
+
+/// Longest steps first, the wrapped function giving each step's duration.
+pub struct LongestFirst<F>(pub F);
+
+impl<F> Priority for LongestFirst<F>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    fn rank(&self, step: &Step) -> i64 {
+        (self.0)(&step.id).unwrap_or(0) as i64
+    }
+}
+
+/// Steps unblocking the most other steps first.
+pub struct MostDependents;
+
+impl Priority for MostDependents {
+    fn rank(&self, step: &Step) -> i64 {
+        step.allows.len() as i64
+    }
+}
+
+impl<F> Priority for F
+where
+    F: Fn(&Step) -> i64,
+{
+    fn rank(&self, step: &Step) -> i64 {
+        self(step)
+    }
+}
+
+/// Steps whose blockers are all completed, best ranked first.
+struct ReadyQueue<'a, P> {
+    tasks: &'a HashMap<String, Step>,
+    policy: &'a P,
+    heap: BinaryHeap<(i64, Reverse<&'a str>)>,
+    blockers: HashMap<&'a str, usize>,
+}
+
+impl<'a, P: Priority> ReadyQueue<'a, P> {
+    fn new(tasks: &'a HashMap<String, Step>, policy: &'a P) -> Self {
+        let mut queue = ReadyQueue {
+            tasks,
+            policy,
+            heap: BinaryHeap::new(),
+            blockers: tasks
+                .values()
+                .map(|s| (s.id.as_str(), s.blocked_by.len()))
+                .collect(),
+        };
+        for s in tasks.values().filter(|s| !s.has_dependencies) {
+            queue.push(s);
+        }
+        queue
+    }
+
+    fn push(&mut self, step: &'a Step) {
+        self.heap
+            .push((self.policy.rank(step), Reverse(step.id.as_str())));
+    }
+
+    fn pop(&mut self) -> Option<&'a Step> {
+        let tasks = self.tasks;
+        self.heap.pop().map(|(_, Reverse(id))| &tasks[id])
+    }
+
+    /// Best ranked step accepted by `filter`, leaving the others queued.
+    fn pop_for<G>(&mut self, filter: G) -> Option<&'a Step>
+    where
+        G: Fn(&Step) -> bool,
+    {
+        let mut skipped = vec![];
+        let mut found = None;
+        while let Some(entry) = self.heap.pop() {
+            if filter(&self.tasks[(entry.1).0]) {
+                found = Some(&self.tasks[(entry.1).0]);
+                break;
             }
+            skipped.push(entry);
         }
-        ready.push(s.clone())
+        self.heap.extend(skipped);
+        found
+    }
+
+    /// Marks `completed` as done, making ready the steps it was the last
+    /// blocker of.
+    fn complete(&mut self, completed: &Step) {
+        let tasks = self.tasks;
+        for next in completed.allows.iter() {
+            let count = self.blockers.get_mut(next.as_str()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.push(&tasks[next]);
+            }
+        }
+    }
+}
+
+/// Order in which a single worker completes the steps, picking among the
+/// ready ones according to `policy`.
+pub fn step_order_with<P: Priority>(
+    input: &Instructions,
+    policy: &P,
+) -> Result<Vec<String>, Error> {
+    let tasks = build_graph(input)?;
+    let mut done = vec![];
+    let mut ready = ReadyQueue::new(&tasks, policy);
+
+    while let Some(next) = ready.pop() {
+        ready.complete(next);
+        done.push(next.id.clone());
     }
-    ready.sort_by(|a, b| b.id.cmp(&a.id));
+
+    Ok(done)
+}
+
+/// Order in which a single worker completes the steps.
+pub fn step_order(input: &Instructions) -> Result<Vec<String>, Error> {
+    step_order_with(input, &Alphabetical)
 }
 
 #[aoc(day7, part1)]
-pub fn solve_part1(input: &[(char, char)]) -> String {
-    let mut tasks = build_graph(input);
-    let mut done = "".to_owned();
-    let mut ready = find_standalone_tasks(&tasks);
+pub fn solve_part1(input: &Instructions) -> Result<String, Error> {
+    Ok(step_order(input)?.concat())
+}
+
+/// A step carried out by a worker from second `start` until second `end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub step: String,
+    pub worker: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Outcome of the simulation: who does what at each second.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    nb_worker: usize,
+    assignments: Vec<Assignment>,
+    total_time: usize,
+}
+
+impl Schedule {
+    pub fn total_time(&self) -> usize {
+        self.total_time
+    }
+
+    /// Steps in the order they were started.
+    pub fn assignments(&self) -> &[Assignment] {
+        &self.assignments
+    }
+
+    pub fn assignment(&self, step: &str) -> Option<&Assignment> {
+        self.assignments.iter().find(|a| a.step == step)
+    }
+
+    /// Step worked on by each worker during second `time`.
+    pub fn workers_at(&self, time: usize) -> Vec<Option<&str>> {
+        let mut workers = vec![None; self.nb_worker];
+        for a in self
+            .assignments
+            .iter()
+            .filter(|a| a.start <= time && time < a.end)
+        {
+            workers[a.worker] = Some(a.step.as_str());
+        }
+        workers
+    }
 
-    while !ready.is_empty() {
-        let next = ready.pop().unwrap();
-        done.push(next.id);
-        tasks.remove(&next.id);
-        unlock_tasks(&next, &tasks, &mut ready);
+    /// Steps completed at the beginning of second `time`, in completion order.
+    pub fn done_at(&self, time: usize) -> Vec<&str> {
+        let mut done: Vec<&Assignment> =
+            self.assignments.iter().filter(|a| a.end <= time).collect();
+        done.sort_by_key(|a| a.end);
+        done.into_iter().map(|a| a.step.as_str()).collect()
     }
 
-    done
+    /// Number of seconds each worker spends without a step.
+    pub fn idle_time(&self) -> Vec<usize> {
+        let mut idle = vec![self.total_time; self.nb_worker];
+        for a in self.assignments.iter() {
+            idle[a.worker] -= a.end - a.start;
+        }
+        idle
+    }
 }
 
-#[allow(dead_code)]
-fn print_state(time: usize, doing: &[(i64, Option<Step>)], done: &str) {
-    print!("{}\t", time);
-    for (_, current) in doing.iter() {
-        let w = match current {
-            None => '.',
-            Some(t) => t.id,
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers: Vec<String> = (1..=self.nb_worker)
+            .map(|w| format!("Worker {}", w))
+            .collect();
+        let width = self
+            .assignments
+            .iter()
+            .map(|a| a.step.len())
+            .chain(headers.iter().map(|h| h.len()))
+            .max()
+            .unwrap_or(0);
+        // single letters are concatenated like in the puzzle, names are not
+        let separator = if self.assignments.iter().all(|a| a.step.len() == 1) {
+            ""
+        } else {
+            " "
         };
-        print!("{}\t", w);
+
+        let mut line = "Second".to_owned();
+        for h in headers.iter() {
+            line.push_str(&format!("   {:<width$}", h, width = width));
+        }
+        writeln!(f, "{}   Done", line)?;
+
+        for time in 0..=self.total_time {
+            let mut line = format!("{:>4}  ", time);
+            for w in self.workers_at(time) {
+                line.push_str(&format!("   {:^width$}", w.unwrap_or("."), width = width));
+            }
+            line.push_str(&format!("   {}", self.done_at(time).join(separator)));
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
     }
+}
+
+/// Simulates `nb_worker` workers completing every step, `duration` giving
+/// the time needed by each step from its name.
+pub fn schedule_with<F>(
+    input: &Instructions,
+    nb_worker: usize,
+    duration: F,
+) -> Result<Schedule, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    schedule_with_priority(input, nb_worker, duration, &Alphabetical)
+}
 
-    println!("{}", done);
+/// Same as `schedule_with`, idle workers picking among the ready steps
+/// according to `policy`.
+pub fn schedule_with_priority<F, P>(
+    input: &Instructions,
+    nb_worker: usize,
+    duration: F,
+    policy: &P,
+) -> Result<Schedule, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+    P: Priority,
+{
+    schedule_workers(input, &vec![Worker::default(); nb_worker], duration, policy)
 }
 
-pub fn generic_solve_part2(input: &[(char, char)], nb_worker: usize, penalty: usize) -> usize {
-    let mut done = "".to_owned();
-    let mut doing: Vec<(i64, Option<Step>)> = vec![(0, None); nb_worker];
-    let mut tasks = build_graph(input);
+/// A member of the crew: `speed` divides the duration of the steps it works
+/// on, and when `skills` is set it only takes the steps listed there.
+#[derive(Debug, Clone)]
+pub struct Worker {
+    speed: f64,
+    skills: Option<HashSet<String>>,
+}
+
+impl Default for Worker {
+    fn default() -> Self {
+        Worker {
+            speed: 1.0,
+            skills: None,
+        }
+    }
+}
+
+impl Worker {
+    pub fn with_speed(self, speed: f64) -> Self {
+        Worker { speed, ..self }
+    }
+
+    pub fn with_skills<I, S>(self, skills: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Worker {
+            skills: Some(skills.into_iter().map(|s| s.into()).collect()),
+            ..self
+        }
+    }
+
+    fn can_take(&self, step: &Step) -> bool {
+        match &self.skills {
+            Some(skills) => skills.contains(&step.id),
+            None => true,
+        }
+    }
+
+    /// Seconds needed for a step lasting `time` at normal speed.
+    fn get_time(&self, time: usize) -> usize {
+        ((time as f64 / self.speed).ceil() as usize).max(1)
+    }
+}
+
+/// Simulates a crew of possibly different workers, idle workers picking the
+/// best ranked ready step they are able to take.
+pub fn schedule_workers<F, P>(
+    input: &Instructions,
+    workers: &[Worker],
+    duration: F,
+    policy: &P,
+) -> Result<Schedule, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+    P: Priority,
+{
+    if workers.is_empty() {
+        bail!("at least one worker is needed");
+    }
+    if let Some(w) = workers
+        .iter()
+        .find(|w| !w.speed.is_finite() || w.speed <= 0.0)
+    {
+        bail!("invalid worker speed {}", w.speed);
+    }
+
+    let tasks = build_graph(input)?;
+    let mut ids: Vec<&String> = tasks.keys().collect();
+    ids.sort();
+    if let Some(id) = ids
+        .into_iter()
+        .find(|id| workers.iter().all(|w| !w.can_take(&tasks[*id])))
+    {
+        bail!("no worker can take step {}", id);
+    }
+
+    let mut assignments: Vec<Assignment> = vec![];
+    let mut done = vec![];
+    let mut doing: Vec<(i64, Option<&Step>)> = vec![(0, None); workers.len()];
     let nb_tasks = tasks.len();
-    let mut ready: Vec<Step> = find_standalone_tasks(&tasks);
+    let mut ready = ReadyQueue::new(&tasks, policy);
 
     let mut time = 0;
 
-    while done.len() != nb_tasks {
+    loop {
         for (eta, current) in doing.iter_mut().filter(|(_, t)| t.is_some()) {
             // advance tasks completion
             *eta -= 1;
             // check if tasks are complete and free workers if it is the case
             if *eta == 0 {
-                let completed = current.as_ref().unwrap();
-                done.push(completed.id);
-                tasks.remove(&completed.id);
-                unlock_tasks(completed, &tasks, &mut ready);
-                *current = None;
+                let completed = current.take().unwrap();
+                ready.complete(completed);
+                done.push(completed.id.as_str());
             }
         }
+        if done.len() == nb_tasks {
+            break;
+        }
+
         // assign tasks to worker if there's tasks ready and workers available
-        for (eta, current) in doing.iter_mut().filter(|(_, t)| t.is_none()) {
-            if let Some(task) = ready.pop() {
-                *eta = task.get_time(penalty) as i64;
+        for (worker, (eta, current)) in doing.iter_mut().enumerate() {
+            if current.is_some() {
+                continue;
+            }
+            if let Some(task) = ready.pop_for(|s| workers[worker].can_take(s)) {
+                let t = workers[worker].get_time(task.get_time(&duration)?);
+                *eta = t as i64;
+                assignments.push(Assignment {
+                    step: task.id.clone(),
+                    worker,
+                    start: time,
+                    end: time + t,
+                });
                 *current = Some(task);
             }
         }
 
-        //print_state(time, &doing, &done);
+        if doing.iter().all(|(_, t)| t.is_none()) {
+            bail!("no step can be started after {}", done.concat());
+        }
+
         time += 1;
     }
 
-    time - 1
+    Ok(Schedule {
+        nb_worker: workers.len(),
+        assignments,
+        total_time: time,
+    })
+}
+
+/// Total time for `nb_worker` workers to complete every step, `duration`
+/// giving the time needed by each step from its name.
+pub fn generic_solve_part2_with<F>(
+    input: &Instructions,
+    nb_worker: usize,
+    duration: F,
+) -> Result<usize, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    Ok(schedule_with(input, nb_worker, duration)?.total_time())
+}
+
+pub fn generic_solve_part2(
+    input: &Instructions,
+    nb_worker: usize,
+    penalty: usize,
+) -> Result<usize, Error> {
+    generic_solve_part2_with(input, nb_worker, |id| letter_duration(id, penalty))
+}
+
+/// Start and end of each step, and the blocker it waits for the longest.
+type Times<'a> = HashMap<&'a str, (usize, usize, Option<&'a str>)>;
+
+/// Earliest times of each step with as many workers as needed.
+fn earliest_times<'a, F>(
+    input: &Instructions,
+    tasks: &'a HashMap<String, Step>,
+    duration: F,
+) -> Result<Times<'a>, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    let mut times = Times::new();
+    for id in step_order(input)?.iter() {
+        let step = &tasks[id];
+        let (start, previous) = step
+            .blocked_by
+            .iter()
+            .map(|b| (times[b.as_str()].1, Some(b.as_str())))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .unwrap_or((0, None));
+        times.insert(
+            &step.id,
+            (start, start + step.get_time(&duration)?, previous),
+        );
+    }
+    Ok(times)
+}
+
+/// Longest chain of steps weighted by their duration, which no number of
+/// workers can beat, with its total duration.
+pub fn critical_path<F>(input: &Instructions, duration: F) -> Result<(usize, Vec<String>), Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    let tasks = build_graph(input)?;
+    let times = earliest_times(input, &tasks, duration)?;
+
+    let (length, mut current) = times
+        .iter()
+        .max_by(|a, b| (a.1).1.cmp(&(b.1).1).then(b.0.cmp(a.0)))
+        .map(|(&id, &(_, end, _))| (end, Some(id)))
+        .unwrap_or((0, None));
+    let mut path = vec![];
+    while let Some(id) = current {
+        path.push(id.to_owned());
+        current = times[id].2;
+    }
+    path.reverse();
+
+    Ok((length, path))
+}
+
+/// Smallest number of workers completing every step within `deadline`,
+/// `None` when the critical path alone is longer than that.
+pub fn workers_for_deadline<F>(
+    input: &Instructions,
+    deadline: usize,
+    duration: F,
+) -> Result<Option<usize>, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    let (bound, _) = critical_path(input, &duration)?;
+    if bound > deadline {
+        return Ok(None);
+    }
+
+    // more workers can make the greedy simulation slower, so no bisection;
+    // with one worker per step every step starts as soon as it is unblocked
+    let nb_tasks = build_graph(input)?.len();
+    for nb_worker in 1..nb_tasks.max(1) {
+        if schedule_with(input, nb_worker, &duration)?.total_time() <= deadline {
+            return Ok(Some(nb_worker));
+        }
+    }
+    Ok(Some(nb_tasks.max(1)))
+}
+
+/// Smallest number of workers completing every step in the time of the
+/// critical path.
+pub fn workers_for_critical_path<F>(input: &Instructions, duration: F) -> Result<usize, Error>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    let (bound, _) = critical_path(input, &duration)?;
+    Ok(workers_for_deadline(input, bound, duration)?.unwrap())
+}
+
+fn dot_escape(id: &str) -> String {
+    id.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Where the start and end times of the steps drawn by `to_dot` come from.
+pub enum Timing<'a> {
+    /// as assigned by a schedule
+    Schedule(&'a Schedule),
+    /// earliest times with as many workers as needed, from step durations
+    Durations(&'a dyn Fn(&str) -> Option<usize>),
+}
+
+/// Graphviz rendering of the steps, each edge going from a step to a step
+/// it unblocks. Nodes are numbered after the single worker order, which is
+/// also drawn as a chain of dashed red edges, and get their start and end
+/// times when a timing is given.
+pub fn to_dot(input: &Instructions, timing: Option<Timing>) -> Result<String, Error> {
+    let tasks = build_graph(input)?;
+    let order = step_order(input)?;
+    let times: HashMap<&str, (usize, usize)> = match timing {
+        None => HashMap::new(),
+        Some(Timing::Schedule(schedule)) => schedule
+            .assignments()
+            .iter()
+            .map(|a| (a.step.as_str(), (a.start, a.end)))
+            .collect(),
+        Some(Timing::Durations(duration)) => earliest_times(input, &tasks, duration)?
+            .into_iter()
+            .map(|(id, (start, end, _))| (id, (start, end)))
+            .collect(),
+    };
+
+    let mut ids: Vec<&String> = tasks.keys().collect();
+    ids.sort();
+
+    let mut dot = "digraph instructions {\n".to_owned();
+    for id in ids.iter() {
+        let rank = order.iter().position(|o| o == *id).unwrap() + 1;
+        let mut label = format!("{}\\n#{}", dot_escape(id), rank);
+        if let Some((start, end)) = times.get(id.as_str()) {
+            label.push_str(&format!("\\n{}-{}", start, end));
+        }
+        dot.push_str(&format!(
+            "    \"{}\" [label=\"{}\"];\n",
+            dot_escape(id),
+            label
+        ));
+    }
+    for id in ids.iter() {
+        for next in tasks[*id].allows.iter() {
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\";\n",
+                dot_escape(id),
+                dot_escape(next)
+            ));
+        }
+    }
+    for pair in order.windows(2) {
+        dot.push_str(&format!(
+            "    \"{}\" -> \"{}\" [style=dashed, color=red, constraint=false];\n",
+            dot_escape(&pair[0]),
+            dot_escape(&pair[1])
+        ));
+    }
+    dot.push_str("}\n");
+
+    Ok(dot)
 }
 
 #[aoc(day7, part2)]
-pub fn solve_part2(input: &[(char, char)]) -> usize {
+pub fn solve_part2(input: &Instructions) -> Result<usize, Error> {
     generic_solve_part2(input, 5, 60)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    const INPUT: &str = "Step C must be finished before step A can begin.\nStep C must be finished before step F can begin.\nStep A must be finished before step B can begin.\nStep A must be finished before step D can begin.\nStep B must be finished before step E can begin.\nStep D must be finished before step E can begin.\nStep F must be finished before step E can begin.";
+
     #[test]
     fn part2() {
-        let input = "Step C must be finished before step A can begin.\nStep C must be finished before step F can begin.\nStep A must be finished before step B can begin.\nStep A must be finished before step D can begin.\nStep B must be finished before step E can begin.\nStep D must be finished before step E can begin.\nStep F must be finished before step E can begin.";
-        assert_eq!(generic_solve_part2(&input_generator(input), 2, 0), 15);
+        assert_eq!(
+            generic_solve_part2(&input_generator(INPUT).unwrap(), 2, 0).unwrap(),
+            15
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let input = "Step C must be finished before step A can begin.\nStep A must be finished before step B can begin.\nStep B must be finished before step D can begin.\nStep D must be finished before step A can begin.";
+        let err = solve_part1(&input_generator(input).unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dependency cycle between steps A -> B -> D -> A"
+        );
+        assert!(generic_solve_part2(&input_generator(input).unwrap(), 2, 0).is_err());
+
+        let input = "Step A must be finished before step A can begin.";
+        let err = solve_part1(&input_generator(input).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "dependency cycle between steps A -> A");
+
+        // every instruction given twice along a long chain
+        let edges: Vec<(String, String)> = (0..40)
+            .flat_map(|i| {
+                let edge = (format!("s{:02}", i), format!("s{:02}", i + 1));
+                vec![edge.clone(), edge]
+            })
+            .collect();
+        let order = step_order(&Instructions::from(edges)).unwrap();
+        assert_eq!(order.len(), 41);
+        assert_eq!(order[40], "s40");
+    }
+
+    #[test]
+    fn named_steps() {
+        let input = "Step fetch must be finished before step build can begin.\nStep configure must be finished before step build can begin.\nStep build must be finished before step test can begin.\nStep build must be finished before step package can begin.";
+        let input = input_generator(input).unwrap();
+        assert_eq!(
+            step_order(&input).unwrap(),
+            vec!["configure", "fetch", "build", "package", "test"]
+        );
+
+        let durations: HashMap<&str, usize> = vec![
+            ("fetch", 3),
+            ("configure", 1),
+            ("build", 10),
+            ("test", 4),
+            ("package", 2),
+        ]
+        .into_iter()
+        .collect();
+        let duration = |id: &str| durations.get(id).cloned();
+        assert_eq!(generic_solve_part2_with(&input, 2, duration).unwrap(), 17);
+        assert_eq!(generic_solve_part2_with(&input, 1, duration).unwrap(), 20);
+
+        let err = generic_solve_part2(&input, 2, 0).unwrap_err();
+        assert_eq!(err.to_string(), "no duration for step configure");
+    }
+
+    #[test]
+    fn schedule() {
+        let schedule = schedule_with(&input_generator(INPUT).unwrap(), 2, |id| {
+            letter_duration(id, 0)
+        })
+        .unwrap();
+        let expected = "Second   Worker 1   Worker 2   Done
+   0        C          .
+   1        C          .
+   2        C          .
+   3        A          F       C
+   4        B          F       CA
+   5        B          F       CA
+   6        D          F       CAB
+   7        D          F       CAB
+   8        D          F       CAB
+   9        D          .       CABF
+  10        E          .       CABFD
+  11        E          .       CABFD
+  12        E          .       CABFD
+  13        E          .       CABFD
+  14        E          .       CABFD
+  15        .          .       CABFDE
+";
+        assert_eq!(schedule.to_string(), expected);
+        assert_eq!(schedule.total_time(), 15);
+        assert_eq!(schedule.idle_time(), vec![0, 9]);
+        assert_eq!(
+            schedule.assignment("F"),
+            Some(&Assignment {
+                step: "F".to_owned(),
+                worker: 1,
+                start: 3,
+                end: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn critical() {
+        let input = input_generator(INPUT).unwrap();
+        let duration = |id: &str| letter_duration(id, 0);
+        assert_eq!(
+            critical_path(&input, duration).unwrap(),
+            (14, vec!["C".to_owned(), "F".to_owned(), "E".to_owned()])
+        );
+        assert_eq!(workers_for_deadline(&input, 13, duration).unwrap(), None);
+        assert_eq!(workers_for_deadline(&input, 15, duration).unwrap(), Some(2));
+        assert_eq!(workers_for_deadline(&input, 21, duration).unwrap(), Some(1));
+        assert_eq!(workers_for_critical_path(&input, duration).unwrap(), 3);
+    }
+
+    #[test]
+    fn dot() {
+        let input = input_generator(
+            "Step C must be finished before step A can begin.\nStep C must be finished before step B can begin.",
+        ).unwrap();
+        let expected = r#"digraph instructions {
+    "A" [label="A\n#2"];
+    "B" [label="B\n#3"];
+    "C" [label="C\n#1"];
+    "C" -> "A";
+    "C" -> "B";
+    "C" -> "A" [style=dashed, color=red, constraint=false];
+    "A" -> "B" [style=dashed, color=red, constraint=false];
+}
+"#;
+        assert_eq!(to_dot(&input, None).unwrap(), expected);
+
+        let schedule = schedule_with(&input, 2, |id| letter_duration(id, 0)).unwrap();
+        let dot = to_dot(&input, Some(Timing::Schedule(&schedule))).unwrap();
+        assert!(dot.contains(r#""B" [label="B\n#3\n3-5"];"#));
+
+        let duration = |id: &str| letter_duration(id, 0);
+        let dot = to_dot(&input, Some(Timing::Durations(&duration))).unwrap();
+        assert!(dot.contains(r#""A" [label="A\n#2\n3-4"];"#));
+        assert!(dot.contains(r#""C" [label="C\n#1\n0-3"];"#));
+    }
+
+    #[test]
+    fn priorities() {
+        let input = input_generator(INPUT).unwrap();
+        let duration = |id: &str| letter_duration(id, 0);
+        assert_eq!(
+            step_order_with(&input, &Alphabetical).unwrap().concat(),
+            "CABDFE"
+        );
+        assert_eq!(
+            step_order_with(&input, &LongestFirst(duration))
+                .unwrap()
+                .concat(),
+            "CFADBE"
+        );
+        let fanout = input_generator(
+            "Step A must be finished before step Z can begin.\nStep B must be finished before step X can begin.\nStep B must be finished before step Y can begin.",
+        ).unwrap();
+        assert_eq!(step_order(&fanout).unwrap().concat(), "ABXYZ");
+        assert_eq!(
+            step_order_with(&fanout, &MostDependents).unwrap().concat(),
+            "BAXYZ"
+        );
+        let reverse = |s: &Step| i64::from(s.id().as_bytes()[0]);
+        assert_eq!(
+            step_order_with(&input, &reverse).unwrap().concat(),
+            "CFADBE"
+        );
+
+        let schedule =
+            schedule_with_priority(&input, 2, duration, &LongestFirst(duration)).unwrap();
+        assert_eq!(schedule.total_time(), 15);
+        assert_eq!(schedule.assignment("F").unwrap().worker, 0);
+    }
+
+    #[test]
+    fn crew() {
+        let input = input_generator(INPUT).unwrap();
+        let duration = |id: &str| letter_duration(id, 0);
+
+        let crew = vec![Worker::default(), Worker::default()];
+        let schedule = schedule_workers(&input, &crew, duration, &Alphabetical).unwrap();
+        assert_eq!(schedule.total_time(), 15);
+
+        let crew = vec![Worker::default().with_speed(2.0), Worker::default()];
+        let schedule = schedule_workers(&input, &crew, duration, &Alphabetical).unwrap();
+        assert_eq!(schedule.assignment("C").unwrap().end, 2);
+        assert_eq!(schedule.total_time(), 11);
+
+        let crew = vec![
+            Worker::default().with_skills(vec!["A", "B", "C", "D", "E"]),
+            Worker::default().with_skills(vec!["F"]),
+        ];
+        let schedule = schedule_workers(&input, &crew, duration, &Alphabetical).unwrap();
+        assert_eq!(schedule.assignment("F").unwrap().worker, 1);
+        assert_eq!(schedule.assignment("D").unwrap().worker, 0);
+        assert_eq!(schedule.idle_time(), vec![0, 9]);
+
+        let crew = vec![Worker::default().with_skills(vec!["A"])];
+        let err = schedule_workers(&input, &crew, duration, &Alphabetical).unwrap_err();
+        assert_eq!(err.to_string(), "no worker can take step B");
+    }
+
+    #[test]
+    fn formats() {
+        let edges = "C -> A\nC -> F\nA -> B\nA -> D\n\nB -> E\nD -> E\nF -> E\n";
+        let json = r#"{
+            "C": [],
+            "A": ["C"],
+            "F": ["C"],
+            "B": ["A"],
+            "D": ["A"],
+            "E": ["B", "D", "F"]
+        }"#;
+        assert_eq!(Format::detect(INPUT), Format::Sentences);
+        assert_eq!(Format::detect("Step -> B\n"), Format::EdgeList);
+        let named: Instructions = "Step -> B\nB -> C".parse().unwrap();
+        assert_eq!(step_order(&named).unwrap(), vec!["Step", "B", "C"]);
+        assert_eq!(Format::detect(edges), Format::EdgeList);
+        assert_eq!(Format::detect(json), Format::Json);
+        for input in [INPUT, edges, json].iter() {
+            let input = input_generator(input).unwrap();
+            assert_eq!(solve_part1(&input).unwrap(), "CABDFE");
+            assert_eq!(generic_solve_part2(&input, 2, 0).unwrap(), 15);
+        }
+
+        let lone = input_generator("A -> B\nC").unwrap();
+        assert_eq!(solve_part1(&lone).unwrap(), "ABC");
+        let lone = input_generator(r#"{"B": ["A"], "A": [], "C": []}"#).unwrap();
+        assert_eq!(solve_part1(&lone).unwrap(), "ABC");
+    }
+
+    #[test]
+    fn format_errors() {
+        let err = input_generator(
+            "Step A must be finished before step B can begin.\nStep B must be done",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: expected \"Step A must be finished before step B can begin.\""
+        );
+
+        let err = "A -> B\nB -> C -> D".parse::<Instructions>().unwrap_err();
+        assert_eq!(err.to_string(), "line 2: expected \"A -> B\" or \"A\"");
+
+        let err = "{\n  \"A\": [],\n  \"B\": [\"A\" \"C\"]\n}"
+            .parse::<Instructions>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "line 3: expected ',' or ']'");
+
+        let err = "{\n  \"A\": [],\n  \"B\": [\n    \"C\"\n  ]\n}"
+            .parse::<Instructions>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 4: unknown step C referenced by step B"
+        );
     }
 }
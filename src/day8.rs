@@ -1,61 +1,459 @@
+use std::error;
+use std::fmt;
+use std::io::{self, BufReader, Read};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
-struct Node {
+#[derive(Debug, PartialEq)]
+pub enum TreeError {
+    /// the data ended while a node still expected numbers
+    Truncated {
+        offset: usize,
+    },
+    /// the root node ended before the data
+    Trailing {
+        offset: usize,
+    },
+    /// a sum doesn't fit in 64 bits
+    Overflow,
+    /// no node at the given path
+    NoSuchNode {
+        path: String,
+    },
+    /// something else than a number in the data
+    InvalidNumber {
+        offset: usize,
+    },
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::Truncated { offset } => {
+                write!(f, "license data truncated at offset {}", offset)
+            }
+            TreeError::Overflow => write!(f, "license value overflow"),
+            TreeError::NoSuchNode { path } => write!(f, "no node at path {}", path),
+            TreeError::InvalidNumber { offset } => {
+                write!(f, "invalid number in license data at offset {}", offset)
+            }
+            TreeError::Io(kind) => write!(f, "can't read license data: {:?}", kind),
+            TreeError::Trailing { offset } => write!(
+                f,
+                "unexpected data after the root node at offset {}",
+                offset
+            ),
+        }
+    }
+}
+
+impl error::Error for TreeError {}
+
+/// A node of the license tree, built by parsing the flat number format or
+/// with a `NodeBuilder`.
+pub struct Node {
+    children: Vec<Node>,
+    metadata: Vec<u64>,
+}
+
+/// Assembles a `Node` child by child.
+///
+/// ```
+/// use aoc2018::day8::Node;
+///
+/// let root = Node::builder()
+///     .child(Node::builder().metadata(&[10, 11, 12]).build())
+///     .metadata(&[1, 1, 2])
+///     .build();
+/// assert_eq!(root.encode(), [1, 3, 0, 3, 10, 11, 12, 1, 1, 2]);
+/// ```
+#[derive(Default)]
+pub struct NodeBuilder {
     children: Vec<Node>,
-    metadata: Vec<u8>,
+    metadata: Vec<u64>,
+}
+
+impl NodeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn children<I: IntoIterator<Item = Node>>(mut self, children: I) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    pub fn metadata(mut self, metadata: &[u64]) -> Self {
+        self.metadata.extend_from_slice(metadata);
+        self
+    }
+
+    pub fn build(self) -> Node {
+        Node {
+            children: self.children,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl Drop for Node {
+    // dropping the children one level at a time would recurse as deep as the tree
+    fn drop(&mut self) {
+        let mut stack: Vec<Node> = std::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
 }
 
 impl Node {
-    fn sum_metadata(&self) -> usize {
-        self.metadata.iter().map(|&d| d as usize).sum()
+    pub fn builder() -> NodeBuilder {
+        NodeBuilder::new()
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    pub fn metadata(&self) -> &[u64] {
+        &self.metadata
+    }
+
+    /// Writes the tree back to the flat format, `parse` giving the same tree
+    /// again.
+    pub fn encode(&self) -> Vec<u64> {
+        let mut data = vec![];
+        let mut stack = vec![(self, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                data.extend_from_slice(&node.metadata);
+            } else {
+                data.push(node.children.len() as u64);
+                data.push(node.metadata.len() as u64);
+                stack.push((node, true));
+                stack.extend(node.children.iter().rev().map(|c| (c, false)));
+            }
+        }
+        data
+    }
+
+    pub fn parse(input: &[u64]) -> Result<Node, TreeError> {
+        build_tree(input)
+    }
+
+    /// Node reached by following child indices from this one, such as
+    /// `"0/2/1"`, the empty path being the node itself.
+    pub fn at(&self, path: &str) -> Option<&Node> {
+        path.split('/')
+            .filter(|p| !p.is_empty())
+            .try_fold(self, |node, p| node.children.get(p.parse::<usize>().ok()?))
+    }
+
+    pub fn inspect(&self, path: &str) -> Result<NodeInfo, TreeError> {
+        let node = self.at(path).ok_or_else(|| TreeError::NoSuchNode {
+            path: path.to_owned(),
+        })?;
+        Ok(NodeInfo {
+            depth: path.split('/').filter(|p| !p.is_empty()).count(),
+            value: node.value()?,
+            metadata_sum: node.recursive_sum_metadata()?,
+            size: node.size(),
+        })
+    }
+
+    pub fn sum_metadata(&self) -> Result<u64, TreeError> {
+        checked_sum(self.metadata.iter().cloned())
+    }
+
+    pub fn recursive_sum_metadata(&self) -> Result<u64, TreeError> {
+        let mut sum: u64 = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            sum = sum
+                .checked_add(node.sum_metadata()?)
+                .ok_or(TreeError::Overflow)?;
+            stack.extend(node.children.iter());
+        }
+        Ok(sum)
+    }
+
+    /// Number of nodes in the subtree.
+    pub fn size(&self) -> usize {
+        self.walk().len()
+    }
+
+    pub fn value(&self) -> Result<u64, TreeError> {
+        Ok(self.values()?[0])
+    }
+
+    /// Nodes of the subtree in pre-order, with their depth and their index
+    /// among their siblings.
+    fn walk(&self) -> Vec<(&Node, usize, usize)> {
+        let mut order = vec![];
+        let mut stack = vec![(self, 0, 0)];
+        while let Some((node, depth, index)) = stack.pop() {
+            order.push((node, depth, index));
+            stack.extend(
+                node.children
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, c)| (c, depth + 1, i)),
+            );
+        }
+        order
+    }
+
+    /// Values of the nodes of the subtree, in pre-order.
+    fn values(&self) -> Result<Vec<u64>, TreeError> {
+        let order = self.walk();
+        let mut sizes = vec![1; order.len()];
+        let mut values = vec![0; order.len()];
+        // in pre-order the first child follows its parent and each sibling
+        // follows the subtree of the previous one, so evaluating from the end
+        // always finds the children done
+        for (k, (node, _, _)) in order.iter().enumerate().rev() {
+            let mut children = Vec::with_capacity(node.children.len());
+            let mut next = k + 1;
+            for _ in node.children.iter() {
+                children.push(next);
+                next += sizes[next];
+            }
+            sizes[k] = next - k;
+            values[k] = if children.is_empty() {
+                node.sum_metadata()?
+            } else {
+                checked_sum(
+                    node.metadata
+                        .iter()
+                        .filter(|&&i| i > 0 && i <= children.len() as u64)
+                        .map(|&i| values[children[(i - 1) as usize]]),
+                )?
+            };
+        }
+        Ok(values)
+    }
+}
+
+/// Computed properties of a node, see `Node::inspect`.
+#[derive(Debug, PartialEq)]
+pub struct NodeInfo {
+    pub depth: usize,
+    pub value: u64,
+    pub metadata_sum: u64,
+    pub size: usize,
+}
+
+/// One line per node, indented by depth, with the path, the header, the
+/// metadata and the value of the node:
+///
+/// ```text
+/// root [2 3] metadata: 1 1 2 => 66
+///   0 [0 3] metadata: 10 11 12 => 33
+/// ```
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = self.values();
+        let mut path: Vec<String> = vec![];
+        for (k, (node, depth, index)) in self.walk().into_iter().enumerate() {
+            path.truncate(depth.saturating_sub(1));
+            if depth > 0 {
+                path.push(index.to_string());
+            }
+            let metadata: Vec<String> = node.metadata.iter().map(|m| m.to_string()).collect();
+            write!(
+                f,
+                "{:indent$}{} [{} {}] metadata: {} => ",
+                "",
+                if depth == 0 {
+                    "root".to_owned()
+                } else {
+                    path.join("/")
+                },
+                node.children.len(),
+                node.metadata.len(),
+                metadata.join(" "),
+                indent = 2 * depth
+            )?;
+            match &values {
+                Ok(values) => writeln!(f, "{}", values[k])?,
+                Err(e) => writeln!(f, "{}", e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn checked_sum<I: Iterator<Item = u64>>(mut iter: I) -> Result<u64, TreeError> {
+    iter.try_fold(0u64, |acc, v| acc.checked_add(v))
+        .ok_or(TreeError::Overflow)
+}
+
+fn build_tree(input: &[u64]) -> Result<Node, TreeError> {
+    let mut offset = 0;
+    let mut next = |count: u64| {
+        if count > (input.len() - offset) as u64 {
+            return Err(TreeError::Truncated {
+                offset: input.len(),
+            });
+        }
+        let count = count as usize;
+        offset += count;
+        Ok(&input[offset - count..offset])
+    };
+
+    // nodes being built: remaining children to read, metadata count and the
+    // children read so far
+    let mut stack: Vec<(u64, u64, Vec<Node>)> = vec![];
+    let header = next(2)?;
+    stack.push((header[0], header[1], Vec::new()));
+
+    let root = loop {
+        let (remaining, nb_metadata, _) = stack.last_mut().unwrap();
+        if *remaining > 0 {
+            *remaining -= 1;
+            let header = next(2)?;
+            stack.push((header[0], header[1], Vec::new()));
+            continue;
+        }
+
+        let metadata = next(*nb_metadata)?.to_vec();
+        let (_, _, children) = stack.pop().unwrap();
+        let node = Node { children, metadata };
+        match stack.last_mut() {
+            Some((_, _, siblings)) => siblings.push(node),
+            None => break node,
+        }
+    };
+
+    if offset < input.len() {
+        return Err(TreeError::Trailing { offset });
     }
+    Ok(root)
+}
 
-    fn recursive_sum_metadata(&self) -> usize {
-        self.children
-            .iter()
-            .map(|c| c.recursive_sum_metadata())
-            .sum::<usize>()
-            + self.sum_metadata()
+/// Whitespace separated numbers read from a byte stream, counted so errors
+/// can report their offset like `build_tree` does.
+struct Numbers<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    count: usize,
+}
+
+impl<R: Read> Numbers<R> {
+    fn new(reader: R) -> Self {
+        Numbers {
+            bytes: BufReader::new(reader).bytes(),
+            count: 0,
+        }
     }
 
-    fn value(&self) -> usize {
-        if self.children.is_empty() {
-            self.sum_metadata()
-        } else {
-            self.metadata
-                .iter()
-                .filter(|&&i| i > 0 && i <= self.children.len() as u8)
-                .map(|&i| self.children[(i - 1) as usize].value())
-                .sum()
+    fn next(&mut self) -> Result<Option<u64>, TreeError> {
+        let mut number: Option<u64> = None;
+        for byte in self.bytes.by_ref() {
+            let byte = byte.map_err(|e| TreeError::Io(e.kind()))?;
+            if byte.is_ascii_digit() {
+                number = number
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|n| n.checked_add(u64::from(byte - b'0')));
+                if number.is_none() {
+                    return Err(TreeError::InvalidNumber { offset: self.count });
+                }
+            } else if !byte.is_ascii_whitespace() {
+                return Err(TreeError::InvalidNumber { offset: self.count });
+            } else if number.is_some() {
+                break;
+            }
         }
+        if number.is_some() {
+            self.count += 1;
+        }
+        Ok(number)
+    }
+
+    fn expect(&mut self) -> Result<u64, TreeError> {
+        self.next()?
+            .ok_or(TreeError::Truncated { offset: self.count })
     }
 }
 
-fn build_tree<'a, I>(iter: &mut I) -> Node
-where
-    I: Iterator<Item = &'a u8>,
-{
-    let nb_children = *iter.next().unwrap();
-    let nb_metadata = *iter.next().unwrap();
-    let children = (0..nb_children).map(|_| build_tree(iter)).collect();
-    let metadata = iter.take(nb_metadata as usize).cloned().collect();
+/// Metadata sum and root value of a tree read from `reader`, in a single
+/// pass that only keeps the ancestors of the current node in memory, along
+/// with the values of their children.
+pub fn evaluate_stream<R: Read>(reader: R) -> Result<(u64, u64), TreeError> {
+    let mut numbers = Numbers::new(reader);
+    let mut sum: u64 = 0;
+
+    // remaining children to read, metadata count and the values of the
+    // children read so far
+    let mut stack: Vec<(u64, u64, Vec<u64>)> = vec![];
+    stack.push((numbers.expect()?, numbers.expect()?, vec![]));
+
+    let value = loop {
+        let (remaining, nb_metadata, _) = stack.last_mut().unwrap();
+        if *remaining > 0 {
+            *remaining -= 1;
+            stack.push((numbers.expect()?, numbers.expect()?, vec![]));
+            continue;
+        }
+
+        let nb_metadata = *nb_metadata;
+        let (_, _, children) = stack.pop().unwrap();
+        let mut value: u64 = 0;
+        for _ in 0..nb_metadata {
+            let m = numbers.expect()?;
+            sum = sum.checked_add(m).ok_or(TreeError::Overflow)?;
+            let v = if children.is_empty() {
+                m
+            } else if m > 0 && m <= children.len() as u64 {
+                children[(m - 1) as usize]
+            } else {
+                0
+            };
+            value = value.checked_add(v).ok_or(TreeError::Overflow)?;
+        }
+        match stack.last_mut() {
+            Some((_, _, siblings)) => siblings.push(value),
+            None => break value,
+        }
+    };
 
-    Node { children, metadata }
+    let offset = numbers.count;
+    if numbers.next()?.is_some() {
+        return Err(TreeError::Trailing { offset });
+    }
+    Ok((sum, value))
 }
 
 #[aoc_generator(day8)]
-pub fn input_generator(input: &str) -> Vec<u8> {
-    input.split(' ').map(|i| i.parse().unwrap()).collect()
+pub fn input_generator(input: &str) -> Result<Vec<u64>, TreeError> {
+    input
+        .split_whitespace()
+        .enumerate()
+        .map(|(offset, i)| {
+            // digits only, like the stream reader
+            i.parse()
+                .ok()
+                .filter(|_| i.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or(TreeError::InvalidNumber { offset })
+        })
+        .collect()
 }
 
 #[aoc(day8, part1)]
-pub fn solve_part1(input: &[u8]) -> usize {
-    build_tree(&mut input.into_iter()).recursive_sum_metadata()
+pub fn solve_part1(input: &[u64]) -> Result<u64, TreeError> {
+    build_tree(input)?.recursive_sum_metadata()
 }
 
 #[aoc(day8, part2)]
-pub fn solve_part2(input: &[u8]) -> usize {
-    build_tree(&mut input.into_iter()).value()
+pub fn solve_part2(input: &[u64]) -> Result<u64, TreeError> {
+    build_tree(input)?.value()
 }
 
 #[cfg(test)]
@@ -63,13 +461,154 @@ mod tests {
     use super::*;
     #[test]
     fn d8_part1() {
-        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
-        assert_eq!(solve_part1(&input), 138);
+        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+        assert_eq!(solve_part1(&input).unwrap(), 138);
     }
 
     #[test]
     fn d8_part2() {
-        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
-        assert_eq!(solve_part2(&input), 66);
+        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+        assert_eq!(solve_part2(&input).unwrap(), 66);
+    }
+
+    #[test]
+    fn d8_malformed() {
+        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1").unwrap();
+        assert_eq!(
+            solve_part1(&input).unwrap_err(),
+            TreeError::Truncated { offset: 15 }
+        );
+        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2 5").unwrap();
+        assert_eq!(
+            solve_part2(&input).unwrap_err(),
+            TreeError::Trailing { offset: 16 }
+        );
+        assert_eq!(
+            solve_part1(&[]).unwrap_err(),
+            TreeError::Truncated { offset: 0 }
+        );
+    }
+
+    #[test]
+    fn d8_deep() {
+        let depth = 200_000;
+        let mut input = [1, 1].repeat(depth);
+        input.extend(&[0, 1, 7]);
+        input.resize(input.len() + depth, 1);
+        assert_eq!(solve_part1(&input).unwrap(), depth as u64 + 7);
+        assert_eq!(solve_part2(&input).unwrap(), 7);
+    }
+
+    #[test]
+    fn d8_round_trip() {
+        let input = input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+        assert_eq!(Node::parse(&input).unwrap().encode(), input);
+
+        let root = Node::builder()
+            .child(Node::builder().metadata(&[10, 11, 12]).build())
+            .child(
+                Node::builder()
+                    .child(Node::builder().metadata(&[99]).build())
+                    .metadata(&[2])
+                    .build(),
+            )
+            .metadata(&[1, 1, 2])
+            .build();
+        let data = root.encode();
+        assert_eq!(data, input);
+        assert_eq!(solve_part2(&data).unwrap(), 66);
+
+        let parsed = Node::parse(&data).unwrap();
+        assert_eq!(parsed.children().len(), 2);
+        assert_eq!(parsed.children()[0].metadata(), &[10, 11, 12]);
+    }
+
+    #[test]
+    fn d8_wide() {
+        // a root with 300 metadata entries all pointing to a single child
+        let mut input = input_generator("1 300 0 2 1000 70000").unwrap();
+        input.resize(306, 1);
+        assert_eq!(solve_part1(&input).unwrap(), 71300);
+        assert_eq!(solve_part2(&input).unwrap(), 300 * 71000);
+
+        let max = u64::MAX;
+        let input = vec![0, 2, max, 1];
+        assert_eq!(solve_part1(&input).unwrap_err(), TreeError::Overflow);
+        let input = vec![0, max];
+        assert_eq!(
+            solve_part1(&input).unwrap_err(),
+            TreeError::Truncated { offset: 2 }
+        );
+    }
+
+    #[test]
+    fn d8_inspect() {
+        let root =
+            Node::parse(&input_generator("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap()).unwrap();
+        assert_eq!(root.at("1/0").unwrap().metadata(), &[99]);
+        assert!(root.at("1/1").is_none());
+        assert_eq!(
+            root.inspect("").unwrap(),
+            NodeInfo {
+                depth: 0,
+                value: 66,
+                metadata_sum: 138,
+                size: 4,
+            }
+        );
+        assert_eq!(
+            root.inspect("1").unwrap(),
+            NodeInfo {
+                depth: 1,
+                value: 0,
+                metadata_sum: 101,
+                size: 2,
+            }
+        );
+        assert_eq!(
+            root.inspect("2").unwrap_err(),
+            TreeError::NoSuchNode {
+                path: "2".to_owned()
+            }
+        );
+
+        let expected = "root [2 3] metadata: 1 1 2 => 66
+  0 [0 3] metadata: 10 11 12 => 33
+  1 [1 1] metadata: 2 => 0
+    1/0 [0 1] metadata: 99 => 99
+";
+        assert_eq!(root.to_string(), expected);
+    }
+
+    #[test]
+    fn d8_stream() {
+        let input = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2\n";
+        assert_eq!(evaluate_stream(input.as_bytes()).unwrap(), (138, 66));
+
+        let depth = 200_000;
+        let deep = format!("{}0 1 7{}", "1 1 ".repeat(depth), " 1".repeat(depth));
+        assert_eq!(
+            evaluate_stream(deep.as_bytes()).unwrap(),
+            (depth as u64 + 7, 7)
+        );
+
+        assert_eq!(
+            evaluate_stream("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1".as_bytes()).unwrap_err(),
+            TreeError::Truncated { offset: 15 }
+        );
+        assert_eq!(
+            evaluate_stream("0 1 7 8".as_bytes()).unwrap_err(),
+            TreeError::Trailing { offset: 3 }
+        );
+        assert_eq!(
+            evaluate_stream("0 1 x".as_bytes()).unwrap_err(),
+            TreeError::InvalidNumber { offset: 2 }
+        );
+        for input in ["0 1 x", "0 1 +7", "0 1 18446744073709551616"].iter() {
+            assert_eq!(
+                input_generator(input).unwrap_err(),
+                evaluate_stream(input.as_bytes()).unwrap_err()
+            );
+        }
     }
 }
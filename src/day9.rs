@@ -1,61 +1,294 @@
+use std::collections::VecDeque;
+
 use aoc_runner_derive::{aoc, aoc_generator};
+use failure::{bail, Error};
+
+/// A game: how many players there are and the value of the last marble.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameConfig {
+    pub nb_players: usize,
+    pub last_marble: usize,
+}
 
 #[aoc_generator(day9)]
-pub fn input_generator(input: &str) -> Vec<usize> {
+pub fn input_generator(input: &str) -> GameConfig {
     let data: Vec<&str> = input.split(' ').collect();
-    let nb_players = data[0].parse::<usize>().unwrap();
-    let last_marble = data[6].parse::<usize>().unwrap();
-    vec![nb_players, last_marble + 1]
+    GameConfig {
+        nb_players: data[0].parse::<usize>().unwrap(),
+        last_marble: data[6].parse::<usize>().unwrap(),
+    }
+}
+
+/// What happens during a turn: either the marble is placed in the circle,
+/// or it is a multiple of the special divisor and the player scores it with
+/// another marble taken out of the circle. Players are numbered from 1 like
+/// in the puzzle.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Placed {
+        player: usize,
+        marble: usize,
+    },
+    Scored {
+        player: usize,
+        marble: usize,
+        removed: usize,
+        points: usize,
+    },
 }
 
-#[allow(dead_code)]
-fn print_circle(circle: &[usize], current: usize) {
-    for c in circle.iter().take(current) {
-        print!(" {} ", c);
+impl Event {
+    pub fn player(&self) -> usize {
+        match self {
+            Event::Placed { player, .. } | Event::Scored { player, .. } => *player,
+        }
     }
 
-    print!("({})", circle[current]);
+    pub fn marble(&self) -> usize {
+        match self {
+            Event::Placed { marble, .. } | Event::Scored { marble, .. } => *marble,
+        }
+    }
 
-    for c in circle.iter().skip(current + 1) {
-        print!(" {} ", c);
+    pub fn points(&self) -> usize {
+        match self {
+            Event::Placed { .. } => 0,
+            Event::Scored { points, .. } => *points,
+        }
     }
-    println!()
 }
 
-#[aoc(day9, part1)]
-pub fn solve_part1(input: &[usize]) -> usize {
-    let nb_players = input[0];
-    let nb_marbles = input[1];
-    let mut circle = Vec::with_capacity(nb_marbles);
-    let mut players = vec![0; nb_players];
-    let mut current = 0;
-    circle.push(0);
-    //println!("[-] (0)");
-    for m in 1..nb_marbles {
-        //print!("[{}] ", m % nb_players + 1);
-        let len = circle.len();
-        let mut pos;
-        if m % 23 == 0 {
-            pos = (len + current - 7) % len;
-            players[m % nb_players] += m + circle.remove(pos);
+/// The rules of the game, `MarbleRules::default()` being the puzzle's.
+#[derive(Debug, Clone)]
+pub struct MarbleRules {
+    /// marbles multiple of this one are scored instead of placed
+    pub divisor: usize,
+    /// how far counter-clockwise from the current marble the scored marble
+    /// is taken
+    pub rewind: usize,
+    /// a new marble is placed right before the marble this far clockwise
+    /// from the current one
+    pub offset: usize,
+    /// marbles clockwise from the current one when the game starts, the
+    /// first marble played being the next number after the largest one
+    pub start: Vec<usize>,
+    /// points won from the scored marble and the marble taken out
+    pub score: fn(usize, usize) -> usize,
+}
+
+impl Default for MarbleRules {
+    fn default() -> Self {
+        MarbleRules {
+            divisor: 23,
+            rewind: 7,
+            offset: 2,
+            start: vec![0],
+            score: |marble, removed| marble + removed,
+        }
+    }
+}
+
+fn rotate_left(circle: &mut VecDeque<usize>, n: usize) {
+    if !circle.is_empty() {
+        let n = n % circle.len();
+        circle.rotate_left(n);
+    }
+}
+
+fn rotate_right(circle: &mut VecDeque<usize>, n: usize) {
+    if !circle.is_empty() {
+        let n = n % circle.len();
+        circle.rotate_right(n);
+    }
+}
+
+/// An endless game, yielding one event per turn. A scoring marble is placed
+/// when the circle is empty.
+struct Game<'a> {
+    rules: &'a MarbleRules,
+    nb_players: usize,
+    // the current marble is always at the back, so both placing and removing
+    // a marble are a few rotations away
+    circle: VecDeque<usize>,
+    turn: usize,
+    marble: usize,
+}
+
+impl<'a> Game<'a> {
+    fn new(nb_players: usize, rules: &'a MarbleRules) -> Result<Self, Error> {
+        if nb_players == 0 {
+            bail!("at least one player is needed");
+        }
+        if rules.divisor == 0 || rules.offset == 0 {
+            bail!("the divisor and the offset must be positive");
+        }
+        let mut circle: VecDeque<usize> = rules.start.iter().cloned().collect();
+        rotate_left(&mut circle, 1);
+        Ok(Game {
+            rules,
+            nb_players,
+            circle,
+            turn: 0,
+            marble: rules.start.iter().max().map_or(0, |m| m + 1),
+        })
+    }
+}
+
+impl<'a> Iterator for Game<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let (player, m) = (self.turn % self.nb_players + 1, self.marble);
+        self.turn += 1;
+        self.marble += 1;
+        let circle = &mut self.circle;
+        if m % self.rules.divisor == 0 && !circle.is_empty() {
+            rotate_right(circle, self.rules.rewind);
+            let removed = circle.pop_back().unwrap();
+            rotate_left(circle, 1);
+            Some(Event::Scored {
+                player,
+                marble: m,
+                removed,
+                points: (self.rules.score)(m, removed),
+            })
         } else {
-            pos = (current + 2) % len;
-            if pos == 0 {
-                pos = len;
-            }
-            circle.insert(pos, m);
+            rotate_left(circle, self.rules.offset - 1);
+            circle.push_back(m);
+            Some(Event::Placed { player, marble: m })
+        }
+    }
+}
+
+/// The game up to the last marble, failing if there is no player, or if the
+/// divisor or the offset is zero.
+fn run<'a>(
+    config: &GameConfig,
+    rules: &'a MarbleRules,
+) -> Result<impl Iterator<Item = Event> + 'a, Error> {
+    let last_marble = config.last_marble;
+    Ok(Game::new(config.nb_players, rules)?.take_while(move |e| e.marble() <= last_marble))
+}
+
+/// Every turn of the game, in order.
+pub fn play(config: &GameConfig) -> Result<Vec<Event>, Error> {
+    play_with_rules(config, &MarbleRules::default())
+}
+
+pub fn play_with_rules(config: &GameConfig, rules: &MarbleRules) -> Result<Vec<Event>, Error> {
+    Ok(run(config, rules)?.collect())
+}
+
+pub fn high_score(config: &GameConfig, rules: &MarbleRules) -> Result<usize, Error> {
+    let mut players = vec![0; config.nb_players];
+    run(config, rules)?.for_each(|e| players[e.player() - 1] += e.points());
+    Ok(players.into_iter().max().unwrap_or(0))
+}
+
+/// Smallest last marble for which some player ends up with at least
+/// `target` points, looking no further than `config.last_marble`.
+pub fn last_marble_for_score(
+    config: &GameConfig,
+    rules: &MarbleRules,
+    target: usize,
+) -> Result<Option<usize>, Error> {
+    let mut players = vec![0; config.nb_players];
+    Ok(run(config, rules)?
+        .find(|e| {
+            let score = &mut players[e.player() - 1];
+            *score += e.points();
+            *score >= target
+        })
+        .map(|e| e.marble()))
+}
+
+/// The circle around the current marble, neighbours being listed nearest
+/// first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub current: usize,
+    pub clockwise: Vec<usize>,
+    pub counter_clockwise: Vec<usize>,
+}
+
+/// The current marble and up to `radius` marbles on each side after `turn`
+/// turns, only playing those turns. `None` if the circle is empty.
+pub fn snapshot(
+    rules: &MarbleRules,
+    turn: usize,
+    radius: usize,
+) -> Result<Option<Snapshot>, Error> {
+    let mut game = Game::new(1, rules)?;
+    game.by_ref().take(turn).for_each(drop);
+    let circle = &game.circle;
+    Ok(circle.back().map(|&current| {
+        let radius = radius.min(circle.len() - 1);
+        Snapshot {
+            current,
+            clockwise: circle.iter().take(radius).cloned().collect(),
+            counter_clockwise: circle.iter().rev().skip(1).take(radius).cloned().collect(),
+        }
+    }))
+}
+
+/// Score of each player after each of their scoring turns, as
+/// `(marble, score)` pairs, the first list being player 1's.
+pub fn score_timelines(nb_players: usize, events: &[Event]) -> Vec<Vec<(usize, usize)>> {
+    let mut timelines: Vec<Vec<(usize, usize)>> = vec![vec![]; nb_players];
+    for e in events.iter() {
+        if let Event::Scored {
+            player,
+            marble,
+            points,
+            ..
+        } = e
+        {
+            let timeline = &mut timelines[player - 1];
+            let score = timeline.last().map_or(0, |&(_, s)| s) + points;
+            timeline.push((*marble, score));
         }
-        current = pos;
-        //print_circle(&circle, current);
     }
-    players.into_iter().max().unwrap()
+    timelines
+}
+
+/// Player leading after each turn, `None` while the best score is shared.
+pub fn leaders(nb_players: usize, events: &[Event]) -> Vec<Option<usize>> {
+    let mut scores = vec![0; nb_players];
+    let mut leader: Option<usize> = None;
+    let mut best = 0;
+    events
+        .iter()
+        .map(|e| {
+            let player = e.player();
+            scores[player - 1] += e.points();
+            let score = scores[player - 1];
+            if score > best {
+                best = score;
+                leader = Some(player);
+            } else if score == best && e.points() > 0 && leader != Some(player) {
+                leader = None;
+            }
+            leader
+        })
+        .collect()
+}
+
+#[aoc(day9, part1)]
+pub fn solve_part1(input: &GameConfig) -> Result<usize, Error> {
+    high_score(input, &MarbleRules::default())
+}
+
+/// Same game as part 1 with a last marble `multiplier` times larger.
+pub fn generic_solve_part2(input: &GameConfig, multiplier: usize) -> Result<usize, Error> {
+    solve_part1(&GameConfig {
+        last_marble: multiplier * input.last_marble,
+        ..*input
+    })
 }
 
 #[aoc(day9, part2)]
-pub fn solve_part2(input: &[usize]) -> usize {
-    let nb_players = input[0];
-    let nb_marbles = 10 * input[1];
-    solve_part1(&[nb_players, nb_marbles])
+pub fn solve_part2(input: &GameConfig) -> Result<usize, Error> {
+    generic_solve_part2(input, 100)
 }
 
 #[cfg(test)]
@@ -73,7 +306,143 @@ mod tests {
         ];
 
         for t in v.iter() {
-            assert_eq!(solve_part1(&input_generator(t.0)), t.1);
+            assert_eq!(solve_part1(&input_generator(t.0)).unwrap(), t.1);
         }
     }
+
+    #[test]
+    fn d9_part2() {
+        let input = input_generator("10 players; last marble is worth 1618 points");
+        assert_eq!(generic_solve_part2(&input, 1).unwrap(), 8317);
+        let input = input_generator("9 players; last marble is worth 25 points");
+        assert_eq!(generic_solve_part2(&input, 100).unwrap(), 22563);
+    }
+
+    #[test]
+    fn d9_events() {
+        let input = input_generator("9 players; last marble is worth 25 points");
+        let events = play(&input).unwrap();
+        assert_eq!(events.len(), 25);
+        assert_eq!(
+            events[0],
+            Event::Placed {
+                player: 1,
+                marble: 1
+            }
+        );
+        assert_eq!(
+            events[22],
+            Event::Scored {
+                player: 5,
+                marble: 23,
+                removed: 9,
+                points: 32
+            }
+        );
+
+        let timelines = score_timelines(9, &events);
+        assert_eq!(timelines[4], vec![(23, 32)]);
+        assert!(timelines[0].is_empty());
+
+        let leaders = leaders(9, &events);
+        assert_eq!(leaders[21], None);
+        assert_eq!(leaders[22], Some(5));
+        assert_eq!(leaders[24], Some(5));
+    }
+
+    #[test]
+    fn d9_rules() {
+        let input = input_generator("9 players; last marble is worth 25 points");
+        assert_eq!(high_score(&input, &MarbleRules::default()).unwrap(), 32);
+
+        // from 0 (4) 2 1 3, marble 5 is scored with the marble two steps
+        // counter-clockwise
+        let rules = MarbleRules {
+            divisor: 5,
+            rewind: 2,
+            score: |marble, removed| marble * removed,
+            ..MarbleRules::default()
+        };
+        let config = GameConfig {
+            nb_players: 2,
+            last_marble: 10,
+        };
+        let events = play_with_rules(&config, &rules).unwrap();
+        assert_eq!(
+            events[4],
+            Event::Scored {
+                player: 1,
+                marble: 5,
+                removed: 3,
+                points: 15
+            }
+        );
+
+        let rules = MarbleRules {
+            start: vec![0, 1, 2],
+            ..MarbleRules::default()
+        };
+        let events = play_with_rules(&input, &rules).unwrap();
+        assert_eq!(
+            events[0],
+            Event::Placed {
+                player: 1,
+                marble: 3
+            }
+        );
+
+        let nobody = GameConfig {
+            nb_players: 0,
+            ..input
+        };
+        let err = solve_part1(&nobody).unwrap_err();
+        assert_eq!(err.to_string(), "at least one player is needed");
+        let rules = MarbleRules {
+            offset: 0,
+            ..MarbleRules::default()
+        };
+        assert!(high_score(&input, &rules).is_err());
+        assert!(snapshot(&rules, 1, 1).is_err());
+    }
+
+    #[test]
+    fn d9_queries() {
+        // 0 16 8 17 4 18 (19) 2 20 10 21 5 22 11 1 12 6 13 3 14 7 15
+        assert_eq!(
+            snapshot(&MarbleRules::default(), 23, 2).unwrap(),
+            Some(Snapshot {
+                current: 19,
+                clockwise: vec![2, 20],
+                counter_clockwise: vec![18, 4],
+            })
+        );
+        let start = snapshot(&MarbleRules::default(), 0, 3).unwrap().unwrap();
+        assert_eq!(start.current, 0);
+        assert!(start.clockwise.is_empty());
+        let rules = MarbleRules {
+            start: vec![],
+            ..MarbleRules::default()
+        };
+        assert_eq!(snapshot(&rules, 0, 1).unwrap(), None);
+
+        let input = input_generator("9 players; last marble is worth 25 points");
+        let rules = MarbleRules::default();
+        assert_eq!(last_marble_for_score(&input, &rules, 32).unwrap(), Some(23));
+        assert_eq!(last_marble_for_score(&input, &rules, 33).unwrap(), None);
+
+        let input = input_generator("10 players; last marble is worth 1618 points");
+        let m = last_marble_for_score(&input, &rules, 8000)
+            .unwrap()
+            .unwrap();
+        let before = GameConfig {
+            last_marble: m - 1,
+            ..input
+        };
+        let at = GameConfig {
+            last_marble: m,
+            ..input
+        };
+        assert!(high_score(&before, &rules).unwrap() < 8000);
+        assert!(high_score(&at, &rules).unwrap() >= 8000);
+    }
 }